@@ -0,0 +1,54 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+const FIBONACCI: &str = r#"
+let n = 1
+let i = 1
+let j = 2
+let tmp = 0
+for n <= 40 {
+   tmp = i
+   i = j
+   j = tmp + j
+   n = n + 1
+}
+"#;
+
+const STRING_BUILDING: &str = r#"
+let i = 0
+let s = ""
+for i < 200 {
+    s = s + i + ","
+    i = i + 1
+}
+"#;
+
+const FUNCTION_CALL: &str = r#"
+def square(n){
+    let r = n * n
+    r
+}
+let i = 0
+let sum = 0
+let tmp = 0
+for i < 200 {
+    tmp = square(i)
+    sum = sum + tmp
+    i = i + 1
+}
+"#;
+
+fn bench_programs(c: &mut Criterion) {
+    for (name, code) in [
+        ("fibonacci", FIBONACCI),
+        ("string_building", STRING_BUILDING),
+        ("function_call", FUNCTION_CALL),
+    ] {
+        let ast = chen_lang::compile(code.to_string()).unwrap();
+        c.bench_function(name, |b| {
+            b.iter(|| chen_lang::execute(black_box(&ast)).unwrap())
+        });
+    }
+}
+
+criterion_group!(benches, bench_programs);
+criterion_main!(benches);
@@ -2,9 +2,11 @@ extern crate clap;
 use std::{
     fs::OpenOptions,
     io::{self, Read},
+    process::ExitCode,
 };
 
 use anyhow::{Ok, Result};
+use chen_lang::token::TokenError;
 use clap::{builder::PossibleValuesParser, Command, CommandFactory, Parser};
 use clap_complete::{generate, Generator, Shell};
 use tracing::{debug, Level};
@@ -37,10 +39,24 @@ enum SubCommand {
     Run {
         ///要执行的源代码文件
         code_file: String,
+        /// 执行完顶层语句后调用 main 函数
+        #[arg(long)]
+        main: bool,
+        /// 只做词法分析, 打印每个 token 和所在的行号
+        #[arg(long, conflicts_with_all = ["main", "time"])]
+        emit_tokens: bool,
+        /// 执行完后在 stderr 打印各个阶段的耗时
+        #[arg(long)]
+        time: bool,
+    },
+    /// 检查源代码里常见的问题, 有问题时退出码不为 0
+    Lint {
+        ///要检查的源代码文件
+        code_file: String,
     },
 }
 
-fn main() -> Result<()> {
+fn main() -> ExitCode {
     let matches = Args::parse();
     tracing_subscriber::fmt()
         .with_max_level(matches.log_level)
@@ -49,18 +65,39 @@ fn main() -> Result<()> {
         .with_thread_names(true)
         .with_thread_ids(true)
         .init();
-    match matches.command {
+    if let Err(err) = run(matches.command) {
+        // 词法分析的错误可以把出错的那一行打印出来
+        match err
+            .downcast_ref::<TokenError>()
+            .and_then(TokenError::snippet)
+        {
+            Some(snippet) => eprintln!("error: {}", snippet),
+            None => eprintln!("error: {:#}", err),
+        }
+        return ExitCode::FAILURE;
+    }
+    ExitCode::SUCCESS
+}
+
+fn run(command: Option<SubCommand>) -> Result<()> {
+    match command {
         None => Args::command().print_help()?,
         Some(command) => match command {
             SubCommand::Completions { shell } => print_completions(shell, &mut Args::command()),
-            SubCommand::Run { code_file } => run_file(code_file)?,
+            SubCommand::Run {
+                code_file,
+                main,
+                emit_tokens,
+                time,
+            } => run_file(code_file, main, emit_tokens, time)?,
+            SubCommand::Lint { code_file } => lint_file(code_file)?,
         },
     }
 
     Ok(())
 }
 
-fn run_file(code_file: String) -> Result<()> {
+fn read_code(code_file: String) -> Result<String> {
     let s = std::env::current_dir()?.join(code_file);
 
     debug!("{:?}", s);
@@ -68,10 +105,40 @@ fn run_file(code_file: String) -> Result<()> {
 
     let mut v = vec![];
     f.read_to_end(&mut v)?;
-    let code = String::from_utf8(v)?;
+    let code = chen_lang::decode_source(v)?;
 
     debug!("{:?}", code);
-    chen_lang::run(code)?;
+    Ok(code)
+}
+
+fn lint_file(code_file: String) -> Result<()> {
+    let ast = chen_lang::compile(read_code(code_file)?)?;
+    let warnings = chen_lang::lint::lint(&ast);
+    for warning in &warnings {
+        println!("warning: {}", warning);
+    }
+    if !warnings.is_empty() {
+        anyhow::bail!("发现 {} 个问题", warnings.len());
+    }
+    Ok(())
+}
+
+fn run_file(code_file: String, call_main: bool, emit_tokens: bool, time: bool) -> Result<()> {
+    let code = read_code(code_file)?;
+    if emit_tokens {
+        for (token, line) in chen_lang::token::tokenlizer_with_line(code)? {
+            println!("{}\t{:?}", line, token);
+        }
+    } else if time {
+        let timings = chen_lang::run_timed(code, call_main)?;
+        eprintln!("tokenize: {:?}", timings.tokenize);
+        eprintln!("parse:    {:?}", timings.parse);
+        eprintln!("execute:  {:?}", timings.execute);
+    } else if call_main {
+        chen_lang::run_main(code)?;
+    } else {
+        chen_lang::run(code)?;
+    }
     Ok(())
 }
 fn print_completions<G: Generator>(gen: G, cmd: &mut Command) {
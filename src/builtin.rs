@@ -0,0 +1,280 @@
+use std::cell::Cell;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::Result;
+
+use crate::context::Context;
+use crate::err_msg;
+use crate::expression::Value;
+
+/// 内置函数, 参数已经求值, 上下文是调用处的上下文
+pub type BuiltinFunction = fn(&Context, Vec<Value>) -> Result<Value>;
+
+/// 根据名字查找内置函数
+pub fn get_builtin(name: &str) -> Option<BuiltinFunction> {
+    let func: BuiltinFunction = match name {
+        "trace" => trace,
+        "abs" => abs,
+        "min" => min,
+        "max" => max,
+        "pow" => pow,
+        "compare" => compare,
+        "len" => len,
+        "type" => type_of,
+        "trim" => trim,
+        "to_upper" => to_upper,
+        "to_lower" => to_lower,
+        "replace" => replace,
+        "contains" => contains,
+        "substring" => substring,
+        "random_int" => random_int,
+        "random_seed" => random_seed,
+        _ => return None,
+    };
+    Some(func)
+}
+
+/// 检查参数个数, 并按顺序取出参数
+fn take_params<const N: usize>(name: &str, params: Vec<Value>) -> Result<[Value; N]> {
+    let count = params.len();
+    params.try_into().map_err(|_| {
+        err_msg(format!(
+            "函数 {} 需要 {} 个参数, 实际传入了 {} 个",
+            name, N, count
+        ))
+    })
+}
+
+/// 取出整数参数
+fn int_param(name: &str, value: &Value) -> Result<i64> {
+    match value {
+        Value::Int(i) => Ok(*i),
+        _ => Err(err_msg(format!(
+            "函数 {} 的参数必须是整数, {:?}",
+            name, value
+        ))),
+    }
+}
+
+/// 取出字符串参数
+fn str_param<'a>(name: &str, value: &'a Value) -> Result<&'a str> {
+    match value {
+        Value::Str(s) => Ok(s),
+        _ => Err(err_msg(format!(
+            "函数 {} 的参数必须是字符串, {:?}",
+            name, value
+        ))),
+    }
+}
+
+/// 整数运算溢出时报错
+fn checked(name: &str, res: Option<i64>) -> Result<Value> {
+    res.map(Value::Int)
+        .ok_or_else(|| err_msg(format!("函数 {} 计算结果溢出", name)))
+}
+
+/// `trace(x)` 打印 x 并换行, 然后把 x 原样返回
+///
+/// 可以放在表达式中间调试, 不影响计算结果
+fn trace(_ctx: &Context, params: Vec<Value>) -> Result<Value> {
+    let [value] = take_params("trace", params)?;
+    println!("{}", value);
+    Ok(value)
+}
+
+/// `abs(x)` 绝对值
+fn abs(_ctx: &Context, params: Vec<Value>) -> Result<Value> {
+    let [x] = take_params("abs", params)?;
+    checked("abs", int_param("abs", &x)?.checked_abs())
+}
+
+/// `min(a, b)` 较小的一个
+fn min(_ctx: &Context, params: Vec<Value>) -> Result<Value> {
+    let [a, b] = take_params("min", params)?;
+    Ok(Value::Int(int_param("min", &a)?.min(int_param("min", &b)?)))
+}
+
+/// `max(a, b)` 较大的一个
+fn max(_ctx: &Context, params: Vec<Value>) -> Result<Value> {
+    let [a, b] = take_params("max", params)?;
+    Ok(Value::Int(int_param("max", &a)?.max(int_param("max", &b)?)))
+}
+
+/// `pow(a, b)` a 的 b 次方, b 不能是负数
+fn pow(_ctx: &Context, params: Vec<Value>) -> Result<Value> {
+    let [a, b] = take_params("pow", params)?;
+    let (a, b) = (int_param("pow", &a)?, int_param("pow", &b)?);
+    let exp = u32::try_from(b).map_err(|_| err_msg(format!("函数 pow 的指数不能是负数, {}", b)))?;
+    checked("pow", a.checked_pow(exp))
+}
+
+/// `compare(a, b)` 三路比较, a 小于、等于、大于 b 时分别返回 -1 0 1
+///
+/// 同类型的值按值比较, 字符串按字典序;
+/// 不同类型之间按 void < bool < int < string 的顺序比较
+fn compare(_ctx: &Context, params: Vec<Value>) -> Result<Value> {
+    let [a, b] = take_params("compare", params)?;
+    let ordering = match (&a, &b) {
+        (Value::Bool(a), Value::Bool(b)) => a.cmp(b),
+        (Value::Int(a), Value::Int(b)) => a.cmp(b),
+        (Value::Str(a), Value::Str(b)) => a.cmp(b),
+        _ => type_rank(&a).cmp(&type_rank(&b)),
+    };
+    Ok(Value::Int(ordering as i64))
+}
+
+/// 不同类型之间比较时的顺序
+fn type_rank(value: &Value) -> u8 {
+    match value {
+        Value::Void => 0,
+        Value::Bool(_) => 1,
+        Value::Int(_) => 2,
+        Value::Str(_) => 3,
+    }
+}
+
+/// `type(x)` 返回类型名: "int" "bool" "string" "void"
+fn type_of(_ctx: &Context, params: Vec<Value>) -> Result<Value> {
+    let [value] = take_params("type", params)?;
+    Ok(value.type_name().into())
+}
+
+/// `len(s)` 字符串的字符个数, 其他类型没有长度
+fn len(_ctx: &Context, params: Vec<Value>) -> Result<Value> {
+    let [value] = take_params("len", params)?;
+    let len = str_param("len", &value)?.chars().count();
+    i64::try_from(len)
+        .map(Value::Int)
+        .map_err(|_| err_msg(format!("字符串太长, 长度 {} 超出了 int 范围", len)))
+}
+
+/// `trim(s)` 去掉首尾的空白
+fn trim(_ctx: &Context, params: Vec<Value>) -> Result<Value> {
+    let [s] = take_params("trim", params)?;
+    Ok(str_param("trim", &s)?.trim().into())
+}
+
+/// `to_upper(s)` 转成大写
+fn to_upper(_ctx: &Context, params: Vec<Value>) -> Result<Value> {
+    let [s] = take_params("to_upper", params)?;
+    Ok(str_param("to_upper", &s)?.to_uppercase().into())
+}
+
+/// `to_lower(s)` 转成小写
+fn to_lower(_ctx: &Context, params: Vec<Value>) -> Result<Value> {
+    let [s] = take_params("to_lower", params)?;
+    Ok(str_param("to_lower", &s)?.to_lowercase().into())
+}
+
+/// `replace(s, from, to)` 把 s 里所有的 from 替换成 to
+fn replace(_ctx: &Context, params: Vec<Value>) -> Result<Value> {
+    let [s, from, to] = take_params("replace", params)?;
+    let s = str_param("replace", &s)?;
+    Ok(
+        s.replace(str_param("replace", &from)?, str_param("replace", &to)?)
+            .into(),
+    )
+}
+
+/// `contains(s, needle)` s 里是否包含 needle
+fn contains(_ctx: &Context, params: Vec<Value>) -> Result<Value> {
+    let [s, needle] = take_params("contains", params)?;
+    Ok(str_param("contains", &s)?
+        .contains(str_param("contains", &needle)?)
+        .into())
+}
+
+/// `substring(s, start, end)` 取出 [start, end) 之间的字符
+///
+/// 下标按字符计算而不是按字节, 超出范围时报错
+fn substring(_ctx: &Context, params: Vec<Value>) -> Result<Value> {
+    let [s, start, end] = take_params("substring", params)?;
+    let s = str_param("substring", &s)?;
+    let (start, end) = (
+        int_param("substring", &start)?,
+        int_param("substring", &end)?,
+    );
+    let len = s.chars().count();
+    match (usize::try_from(start), usize::try_from(end)) {
+        (Ok(start), Ok(end)) if start <= end && end <= len => Ok(s
+            .chars()
+            .skip(start)
+            .take(end - start)
+            .collect::<String>()
+            .into()),
+        _ => Err(err_msg(format!(
+            "substring 下标越界, 字符串长度 {}, 下标 [{}, {})",
+            len, start, end
+        ))),
+    }
+}
+
+/// 随机数生成器的状态, 每次执行的上下文各有一份, 子上下文和被调用的函数共用
+#[derive(Debug)]
+pub(crate) struct RandomState(Cell<u64>);
+
+impl Default for RandomState {
+    /// 默认用当前时间做种子, 再加上一个计数, 同时创建的两个上下文种子也不一样
+    fn default() -> Self {
+        static COUNT: AtomicU64 = AtomicU64::new(0);
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|it| it.as_nanos() as u64)
+            .unwrap_or_default();
+        RandomState(Cell::new(split_mix(
+            nanos.wrapping_add(COUNT.fetch_add(1, Ordering::Relaxed)),
+        )))
+    }
+}
+
+impl RandomState {
+    /// 重新设置种子
+    fn seed(&self, seed: u64) {
+        self.0.set(split_mix(seed));
+    }
+
+    /// xorshift64* 生成下一个随机数
+    fn next(&self) -> u64 {
+        let mut x = self.0.get();
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        self.0.set(x);
+        x.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+}
+
+/// 把种子打散, 保证状态不为 0
+fn split_mix(seed: u64) -> u64 {
+    let mut z = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    (z ^ (z >> 31)) | 1
+}
+
+/// `random_seed(n)` 设置随机数种子, 相同的种子得到相同的随机数序列
+fn random_seed(ctx: &Context, params: Vec<Value>) -> Result<Value> {
+    let [seed] = take_params("random_seed", params)?;
+    let seed = int_param("random_seed", &seed)?;
+    ctx.random_state().seed(seed as u64);
+    Ok(Value::Void)
+}
+
+/// `random_int(min, max)` 返回 [min, max] 之间的随机整数, 包括 max
+fn random_int(ctx: &Context, params: Vec<Value>) -> Result<Value> {
+    let [min, max] = take_params("random_int", params)?;
+    let (min, max) = (
+        int_param("random_int", &min)?,
+        int_param("random_int", &max)?,
+    );
+    if min > max {
+        return Err(err_msg(format!(
+            "random_int 的范围不对, {} 大于 {}",
+            min, max
+        )));
+    }
+    let range = (max as i128 - min as i128 + 1) as u128;
+    let offset = ctx.random_state().next() as u128 % range;
+    Ok(Value::Int((min as i128 + offset as i128) as i64))
+}
@@ -1,9 +1,11 @@
 use std::cell::RefCell;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
 use std::string::ToString;
 
 use tracing::warn;
 
+use crate::builtin::RandomState;
 use crate::expression::*;
 
 trait Var {
@@ -58,13 +60,39 @@ impl Context<'_> {
     pub(crate) fn init_with_parent_context<'b>(parent_ctx: &'b Context<'b>) -> Context<'b> {
         Context {
             parent: Some(parent_ctx),
-            ..Default::default()
+            variables: HashMap::new(),
+            functions: HashMap::new(),
+            call_depth: parent_ctx.call_depth,
+            max_call_depth: parent_ctx.max_call_depth,
+            disabled_builtins: parent_ctx.disabled_builtins.clone(),
+            random_state: parent_ctx.random_state.clone(),
+        }
+    }
+
+    /// 函数调用时使用的上下文, 没有父级上下文, 嵌套层数比调用处多一层
+    #[inline]
+    pub(crate) fn init_for_call(caller: &Context) -> Context<'static> {
+        Context {
+            parent: None,
+            variables: HashMap::new(),
+            functions: HashMap::new(),
+            call_depth: caller.call_depth + 1,
+            max_call_depth: caller.max_call_depth,
+            disabled_builtins: caller.disabled_builtins.clone(),
+            random_state: caller.random_state.clone(),
         }
     }
 }
 
 /// 程序上下文
-#[derive(Debug, Default)]
+///
+/// 作用域规则:
+/// - 每个语句块 (`{}`、if 分支、每一次循环) 执行时都会创建一个子上下文
+/// - `let`/`const` 总是声明在当前上下文里, 同一个上下文里不能重复声明,
+///   子上下文里可以声明同名变量, 遮蔽外层的变量, 语句块结束后外层变量不受影响
+/// - 读取和赋值从当前上下文开始逐级往父级查找, 所以赋值会修改最近的一个同名变量
+/// - 函数调用时只能看到自己的参数和函数, 看不到调用处的变量
+#[derive(Debug)]
 pub struct Context<'a> {
     /// 父级上下文
     parent: Option<&'a Context<'a>>,
@@ -74,19 +102,62 @@ pub struct Context<'a> {
 
     /// 方法池
     functions: HashMap<String, FunctionStatement>,
+
+    /// 函数调用的嵌套层数, 顶层代码是 0
+    pub(crate) call_depth: usize,
+
+    /// 函数调用最多能嵌套的层数, 子上下文和被调用的函数都沿用同一个值
+    pub(crate) max_call_depth: usize,
+
+    /// 被禁用的内置函数, 子上下文和被调用的函数共用同一份
+    disabled_builtins: Rc<HashSet<String>>,
+
+    /// 随机数生成器的状态, 子上下文和被调用的函数共用同一份
+    random_state: Rc<RandomState>,
+}
+
+impl Default for Context<'_> {
+    fn default() -> Self {
+        Context {
+            parent: None,
+            variables: HashMap::new(),
+            functions: HashMap::new(),
+            call_depth: 0,
+            max_call_depth: DEFAULT_MAX_CALL_DEPTH,
+            disabled_builtins: Rc::default(),
+            random_state: Rc::default(),
+        }
+    }
 }
 
 impl Context<'_> {
+    /// 设置函数调用最多能嵌套的层数, 超过之后调用会返回错误, 而不是让进程栈溢出
+    ///
+    /// 只影响用这个上下文执行的代码, 要在执行之前设置
+    pub fn set_max_call_depth(&mut self, depth: usize) {
+        self.max_call_depth = depth;
+    }
+
+    /// 禁用一个内置函数, 用来运行不信任的代码, 之后调用它会返回错误
+    ///
+    /// 和 [`Context::set_max_call_depth`] 一样只影响用这个上下文执行的代码
+    pub fn disable_builtin(&mut self, name: &str) {
+        Rc::make_mut(&mut self.disabled_builtins).insert(name.to_string());
+    }
+
+    /// 内置函数是否被 [`Context::disable_builtin`] 禁用了
+    pub fn is_builtin_disabled(&self, name: &str) -> bool {
+        self.disabled_builtins.contains(name)
+    }
+
+    pub(crate) fn random_state(&self) -> &RandomState {
+        &self.random_state
+    }
+
     pub fn get_function(&self, name: &str) -> Option<&FunctionStatement> {
         match self.functions.get(name) {
             Some(val) => Some(val),
-            None => match &self.parent {
-                Some(scoop) => scoop.get_function(name),
-                None => {
-                    warn!("获取一个不存在的函数,{}", name);
-                    None
-                }
-            },
+            None => self.parent.and_then(|scoop| scoop.get_function(name)),
         }
     }
 
@@ -94,18 +165,44 @@ impl Context<'_> {
         &self.functions
     }
 
+    /// 当前上下文和所有父级上下文里能看到的函数, 内层的同名函数优先
+    pub(crate) fn get_visible_functions(&self) -> HashMap<String, FunctionStatement> {
+        let mut functions = match self.parent {
+            Some(parent) => parent.get_visible_functions(),
+            None => HashMap::new(),
+        };
+        for (name, func) in &self.functions {
+            functions.insert(name.clone(), func.clone());
+        }
+        functions
+    }
+
+    /// 最近的同名函数是不是定义在最外层的上下文里 (函数调用时就是函数自己的上下文)
+    pub(crate) fn is_root_function(&self, name: &str) -> bool {
+        match (self.functions.contains_key(name), self.parent) {
+            (true, parent) => parent.is_none(),
+            (false, Some(parent)) => parent.is_root_function(name),
+            (false, None) => false,
+        }
+    }
+
+    pub(crate) fn into_functions(self) -> HashMap<String, FunctionStatement> {
+        self.functions
+    }
+
     pub fn insert_function(&mut self, name: &str, func: FunctionStatement) -> bool {
-        match self.get_var(name) {
-            Some(_) => {
-                warn!("添加一个已经存在的变量，{}", name);
-                false
-            }
-            None => {
-                self.functions.insert(name.to_string(), func);
-                true
-            }
+        if self.has_var(name) {
+            warn!("添加一个已经存在的变量，{}", name);
+            return false;
         }
+        self.functions.insert(name.to_string(), func);
+        true
     }
+    /// 变量是否存在, 不存在时不打日志
+    pub(crate) fn has_var(&self, name: &str) -> bool {
+        self.variables.contains_key(name) || self.parent.is_some_and(|it| it.has_var(name))
+    }
+
     pub(crate) fn get_var(&self, name: &str) -> Option<Value> {
         match self.variables.get(name) {
             Some(val) => Some(val.get()),
@@ -1,50 +1,339 @@
 use std::clone::Clone;
 use std::collections::VecDeque;
-use std::fmt::{Debug, Formatter};
+use std::fmt::{Debug, Display, Formatter};
 use std::rc::Rc;
 use std::result::Result::Err;
 
 use anyhow::Result;
 
+use crate::builtin::get_builtin;
 use crate::context::*;
 use crate::err_msg;
 use crate::token::Operator;
 
 /// 表达式  核心对象
 /// 一切语法都是表达式
-
 pub trait Expression: Debug {
     ///
     /// 表达式执行的方法
     ///
     fn evaluate(&self, ctx: &mut Context) -> Result<Value>;
+
+    ///
+    /// 表达式对应的语法树节点, 给 [`Visitor`] 用
+    ///
+    fn node(&self) -> Node<'_>;
+}
+
+/// 语法树节点, 每一种表达式对应一个
+#[derive(Debug, Clone, Copy)]
+pub enum Node<'a> {
+    /// 函数调用
+    Call(&'a CallFunctionStatement),
+    /// 返回语句
+    Return(&'a ReturnStatement),
+    /// 函数定义
+    Function(&'a FunctionStatement),
+    /// 二元运算
+    Binary(&'a BinaryStatement),
+    /// 取反
+    Not(&'a NotStatement),
+    /// 按位取反
+    BitNot(&'a BitNotStatement),
+    /// `??`
+    Coalesce(&'a CoalesceStatement),
+    /// 打印
+    Print(&'a PrintStatement),
+    /// 声明
+    Declare(&'a DeclareStatement),
+    /// 赋值
+    Assign(&'a AssignStatement),
+    /// 语句块
+    Block(&'a BlockStatement),
+    /// 循环
+    Loop(&'a LoopStatement),
+    /// 条件
+    If(&'a IfStatement),
+    /// 变量
+    Variable(&'a VariableStatement),
+    /// 常量
+    Value(&'a Value),
+}
+
+/// 语法树的访问者
+///
+/// 每个 `visit_*` 方法默认都会接着访问子节点, 实现的时候只需要重写关心的节点。
+/// 重写的方法里如果还要访问子节点, 调用对应的 `walk_*` 函数
+pub trait Visitor {
+    /// 访问任意一个表达式, 默认按节点类型分发到下面的方法
+    fn visit_expression(&mut self, expr: &dyn Expression) {
+        walk_expression(self, expr)
+    }
+
+    fn visit_block(&mut self, block: &BlockStatement) {
+        walk_block(self, block)
+    }
+
+    fn visit_call(&mut self, call: &CallFunctionStatement) {
+        walk_call(self, call)
+    }
+
+    fn visit_return(&mut self, stmt: &ReturnStatement) {
+        self.visit_expression(stmt.expr.as_ref())
+    }
+
+    fn visit_function(&mut self, func: &FunctionStatement) {
+        self.visit_block(&func.body)
+    }
+
+    fn visit_binary(&mut self, expr: &BinaryStatement) {
+        self.visit_expression(expr.left.as_ref());
+        self.visit_expression(expr.right.as_ref());
+    }
+
+    fn visit_not(&mut self, expr: &NotStatement) {
+        self.visit_expression(expr.expr.as_ref())
+    }
+
+    fn visit_bit_not(&mut self, expr: &BitNotStatement) {
+        self.visit_expression(expr.expr.as_ref())
+    }
+
+    fn visit_coalesce(&mut self, expr: &CoalesceStatement) {
+        self.visit_expression(expr.left.as_ref());
+        self.visit_expression(expr.right.as_ref());
+    }
+
+    fn visit_print(&mut self, stmt: &PrintStatement) {
+        self.visit_expression(stmt.expression.as_ref())
+    }
+
+    fn visit_declare(&mut self, stmt: &DeclareStatement) {
+        self.visit_expression(stmt.right.as_ref())
+    }
+
+    fn visit_assign(&mut self, stmt: &AssignStatement) {
+        self.visit_expression(stmt.right.as_ref())
+    }
+
+    fn visit_loop(&mut self, stmt: &LoopStatement) {
+        self.visit_expression(stmt.predict.as_ref());
+        self.visit_block(&stmt.loop_block);
+    }
+
+    fn visit_if(&mut self, stmt: &IfStatement) {
+        self.visit_expression(stmt.predict.as_ref());
+        self.visit_block(&stmt.if_block);
+        self.visit_block(&stmt.else_block);
+    }
+
+    fn visit_variable(&mut self, _var: &VariableStatement) {}
+
+    fn visit_value(&mut self, _value: &Value) {}
+}
+
+/// 按节点类型调用访问者对应的方法
+pub fn walk_expression<V: Visitor + ?Sized>(visitor: &mut V, expr: &dyn Expression) {
+    match expr.node() {
+        Node::Call(it) => visitor.visit_call(it),
+        Node::Return(it) => visitor.visit_return(it),
+        Node::Function(it) => visitor.visit_function(it),
+        Node::Binary(it) => visitor.visit_binary(it),
+        Node::Not(it) => visitor.visit_not(it),
+        Node::BitNot(it) => visitor.visit_bit_not(it),
+        Node::Coalesce(it) => visitor.visit_coalesce(it),
+        Node::Print(it) => visitor.visit_print(it),
+        Node::Declare(it) => visitor.visit_declare(it),
+        Node::Assign(it) => visitor.visit_assign(it),
+        Node::Block(it) => visitor.visit_block(it),
+        Node::Loop(it) => visitor.visit_loop(it),
+        Node::If(it) => visitor.visit_if(it),
+        Node::Variable(it) => visitor.visit_variable(it),
+        Node::Value(it) => visitor.visit_value(it),
+    }
+}
+
+/// 依次访问语句块里的每一条语句
+pub fn walk_block<V: Visitor + ?Sized>(visitor: &mut V, block: &BlockStatement) {
+    for expr in block {
+        visitor.visit_expression(expr.as_ref());
+    }
+}
+
+/// 依次访问函数调用的每一个参数
+pub fn walk_call<V: Visitor + ?Sized>(visitor: &mut V, call: &CallFunctionStatement) {
+    for param in &call.params {
+        visitor.visit_expression(param.as_ref());
+    }
 }
 
 #[derive(Debug)]
 pub struct CallFunctionStatement {
     pub function_name: String,
     pub params: Vec<Box<dyn Expression>>,
+    /// 所在的行号
+    pub line: usize,
 }
 
 impl Expression for CallFunctionStatement {
+    fn node(&self) -> Node<'_> {
+        Node::Call(self)
+    }
+
     fn evaluate(&self, ctx: &mut Context) -> Result<Value> {
-        let params: Vec<_> = self
+        let params = self
             .params
             .iter()
-            .map(|it| it.evaluate(ctx).unwrap())
-            .collect();
-        let func = ctx.get_function(self.function_name.as_str()).unwrap();
-        let mut new_ctx = Context::default();
-        for (idx, param) in params.iter().enumerate() {
-            new_ctx.insert_var(func.params[idx].as_str(), param.clone(), VarType::Let);
+            .map(|it| it.evaluate(ctx))
+            .collect::<Result<Vec<_>>>()?;
+        call_function(ctx, self.function_name.as_str(), params)
+    }
+}
+
+/// [`Context::default`] 的函数调用最大嵌套层数
+///
+/// 解释器每调用一层函数都要占用好几个 Rust 栈帧, debug 编译时一层要 8KB 左右,
+/// 这个层数在 2MB 的栈 (Rust 新建线程的默认大小) 里也不会溢出.
+/// [`crate::run`] 这些函数在栈足够大的线程里执行, 会调高到 [`crate::LARGE_STACK_MAX_CALL_DEPTH`]
+pub const DEFAULT_MAX_CALL_DEPTH: usize = 128;
+
+/// 用已经求值的参数调用函数
+///
+/// 名字的查找顺序:
+/// 1. 自定义函数
+/// 2. 变量, 变量不能调用, 所以同名的变量会让内置函数也调用不了
+/// 3. 内置函数
+///
+/// 每调用一层函数, 嵌套层数加一, 超过 [`Context::set_max_call_depth`] 设置的层数时返回错误
+///
+/// 函数体里的 `return f(...)` 是尾调用, 不会再嵌套一层, 而是在这里循环执行被调用的函数
+pub fn call_function(ctx: &Context, name: &str, params: Vec<Value>) -> Result<Value> {
+    let func = match ctx.get_function(name) {
+        Some(func) => func,
+        None if ctx.has_var(name) => {
+            return Err(err_msg(format!("{} 不是函数, 不能调用", name)));
+        }
+        None => match get_builtin(name) {
+            Some(_) if ctx.is_builtin_disabled(name) => {
+                return Err(err_msg(format!("内置函数 {} 在沙箱里被禁用了", name)));
+            }
+            Some(builtin) => return builtin(ctx, params),
+            None => {
+                return Err(err_msg(format!("调用了一个不存在的函数, {}", name)));
+            }
+        },
+    };
+    if ctx.call_depth >= ctx.max_call_depth {
+        return Err(err_msg(format!(
+            "函数调用层数超过了 {} 层, 可能是无限递归, 调用 {} 时栈溢出",
+            ctx.max_call_depth, name
+        )));
+    }
+    let mut func = func.clone();
+    let mut params = params;
+    let mut functions = ctx.get_visible_functions();
+    loop {
+        if params.len() != func.params.len() {
+            return Err(err_msg(format!(
+                "函数 {} 需要 {} 个参数, 实际传入了 {} 个",
+                func.name,
+                func.params.len(),
+                params.len()
+            )));
         }
-        for (name, func) in ctx.get_all_function() {
-            new_ctx.insert_function(name, func.clone());
+        let mut new_ctx = Context::init_for_call(ctx);
+        for (idx, param) in params.into_iter().enumerate() {
+            new_ctx.insert_var(func.params[idx].as_str(), param, VarType::Let);
+        }
+        for (name, func) in functions {
+            new_ctx.insert_function(&name, func);
+        }
+        match func.body.evaluate(&mut new_ctx) {
+            Err(err) => match err.downcast::<ReturnValue>() {
+                Ok(ReturnValue(value)) => return Ok(value),
+                Err(err) => match err.downcast::<TailCall>() {
+                    Ok(tail_call) => {
+                        functions = new_ctx.into_functions();
+                        func = functions
+                            .get(&tail_call.name)
+                            .ok_or_else(|| {
+                                err_msg(format!("尾调用了一个不存在的函数, {}", tail_call.name))
+                            })?
+                            .clone();
+                        params = tail_call.params;
+                    }
+                    Err(err) => return Err(err),
+                },
+            },
+            res => return res,
+        }
+    }
+}
+
+/// 返回语句, 没有表达式时返回 `Value::Void`
+#[derive(Debug)]
+pub struct ReturnStatement {
+    /// 返回值
+    pub expr: Box<dyn Expression>,
+    /// 所在的行号
+    pub line: usize,
+}
+
+impl Expression for ReturnStatement {
+    fn node(&self) -> Node<'_> {
+        Node::Return(self)
+    }
+
+    fn evaluate(&self, ctx: &mut Context) -> Result<Value> {
+        if let Node::Call(call) = self.expr.node() {
+            // 顶层代码里的 return 不是尾调用, 照常报错
+            if ctx.call_depth > 0 && ctx.is_root_function(&call.function_name) {
+                let params = call
+                    .params
+                    .iter()
+                    .map(|it| it.evaluate(ctx))
+                    .collect::<Result<Vec<_>>>()?;
+                return Err(TailCall {
+                    name: call.function_name.clone(),
+                    params,
+                }
+                .into());
+            }
         }
-        func.body.evaluate(&mut new_ctx)
+        let value = self.expr.evaluate(ctx)?;
+        Err(ReturnValue(value).into())
     }
 }
 
+/// `return` 的值当作错误一层层往外传, 直到函数调用的地方再变回返回值
+///
+/// 如果一直传到了最外层, 说明 `return` 没有写在函数里
+#[derive(Debug)]
+struct ReturnValue(Value);
+
+impl Display for ReturnValue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "return 只能在函数里使用")
+    }
+}
+
+impl std::error::Error for ReturnValue {}
+
+/// 尾调用, 和 [`ReturnValue`] 一样往外传, 由 [`call_function`] 接着执行被调用的函数
+#[derive(Debug)]
+struct TailCall {
+    name: String,
+    params: Vec<Value>,
+}
+
+impl Display for TailCall {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "尾调用 {} 没有被函数调用处理", self.name)
+    }
+}
+
+impl std::error::Error for TailCall {}
+
 #[derive(Debug, Clone)]
 pub struct FunctionStatement {
     pub name: String,
@@ -53,6 +342,10 @@ pub struct FunctionStatement {
 }
 
 impl Expression for FunctionStatement {
+    fn node(&self) -> Node<'_> {
+        Node::Function(self)
+    }
+
     fn evaluate(&self, ctx: &mut Context) -> Result<Value> {
         ctx.insert_function(self.name.as_str(), self.clone());
         Ok(Value::Void)
@@ -72,32 +365,55 @@ pub struct BinaryStatement {
 }
 
 impl Expression for BinaryStatement {
+    fn node(&self) -> Node<'_> {
+        Node::Binary(self)
+    }
+
     fn evaluate(&self, ctx: &mut Context) -> Result<Value> {
         let l = self.left.evaluate(ctx)?;
         let r = self.right.evaluate(ctx)?;
         match self.operator {
             Operator::ADD => match (l, r) {
-                (Value::Int(l_int), Value::Int(r_int)) => Ok(Value::Int(l_int + r_int)),
-                (Value::Str(a), b) => Ok(Value::Str(format!("{}{}", a, b.to_string()))),
-                (a, Value::Str(b)) => Ok(Value::Str(format!("{}{}", a.to_string(), b))),
+                (Value::Int(l_int), Value::Int(r_int)) => {
+                    checked_int(l_int.checked_add(r_int), l_int, "+", r_int)
+                }
+                (Value::Str(a), b) => Ok(Value::Str(format!("{}{}", a, b))),
+                (a, Value::Str(b)) => Ok(Value::Str(format!("{}{}", a, b))),
                 _ => Err(err_msg("不是 int string 类型不能做加法")),
             },
             Operator::Subtract => match (l, r) {
-                (Value::Int(l_int), Value::Int(r_int)) => Ok(Value::Int(l_int - r_int)),
+                (Value::Int(l_int), Value::Int(r_int)) => {
+                    checked_int(l_int.checked_sub(r_int), l_int, "-", r_int)
+                }
                 _ => Err(err_msg("不是 int 类型不能做减法")),
             },
             Operator::Multiply => match (l, r) {
-                (Value::Int(l_int), Value::Int(r_int)) => Ok(Value::Int(l_int * r_int)),
+                (Value::Int(l_int), Value::Int(r_int)) => {
+                    checked_int(l_int.checked_mul(r_int), l_int, "*", r_int)
+                }
                 _ => Err(err_msg("不是 int 类型不能做乘法")),
             },
             Operator::Divide => match (l, r) {
-                (Value::Int(l_int), Value::Int(r_int)) => Ok(Value::Int(l_int / r_int)),
+                (Value::Int(_), Value::Int(0)) => Err(err_msg("除数不能为 0")),
+                (Value::Int(l_int), Value::Int(r_int)) => {
+                    checked_int(l_int.checked_div(r_int), l_int, "/", r_int)
+                }
                 _ => Err(err_msg("不是 int 类型不能做除法")),
             },
             Operator::Mod => match (l, r) {
-                (Value::Int(l_int), Value::Int(r_int)) => Ok(Value::Int(l_int % r_int)),
+                (Value::Int(_), Value::Int(0)) => Err(err_msg("除数不能为 0")),
+                (Value::Int(l_int), Value::Int(r_int)) => {
+                    checked_int(l_int.checked_rem(r_int), l_int, "%", r_int)
+                }
                 _ => Err(err_msg("不是 int 类型不能做余数运算")),
             },
+            Operator::Power => match (l, r) {
+                (Value::Int(l_int), Value::Int(r_int)) => match u32::try_from(r_int) {
+                    Ok(exp) => checked_int(l_int.checked_pow(exp), l_int, "**", r_int),
+                    Err(_) => Err(err_msg(format!("指数不能是负数, {}", r_int))),
+                },
+                _ => Err(err_msg("不是 int 类型不能做乘方运算")),
+            },
             Operator::And => match (l, r) {
                 (Value::Bool(l_b), Value::Bool(r_b)) => Ok(Value::Bool(l_b && r_b)),
                 _ => Err(err_msg("不是 bool 类型不能做逻辑运算")),
@@ -122,14 +438,55 @@ impl Expression for BinaryStatement {
                 (Value::Int(l_int), Value::Int(r_int)) => Ok(Value::Bool(l_int <= r_int)),
                 _ => Err(err_msg("不是 int 类型不能做比较运算")),
             },
+            Operator::BitAnd => match (l, r) {
+                (Value::Int(l_int), Value::Int(r_int)) => Ok(Value::Int(l_int & r_int)),
+                _ => Err(err_msg("不是 int 类型不能做位运算")),
+            },
+            Operator::BitOr => match (l, r) {
+                (Value::Int(l_int), Value::Int(r_int)) => Ok(Value::Int(l_int | r_int)),
+                _ => Err(err_msg("不是 int 类型不能做位运算")),
+            },
+            Operator::BitXor => match (l, r) {
+                (Value::Int(l_int), Value::Int(r_int)) => Ok(Value::Int(l_int ^ r_int)),
+                _ => Err(err_msg("不是 int 类型不能做位运算")),
+            },
+            Operator::ShiftLeft => match (l, r) {
+                (Value::Int(l_int), Value::Int(r_int)) => u32::try_from(r_int)
+                    .ok()
+                    .and_then(|r_int| l_int.checked_shl(r_int))
+                    .map(Value::Int)
+                    .ok_or_else(|| err_msg(format!("移位位数不对, {}", r_int))),
+                _ => Err(err_msg("不是 int 类型不能做位运算")),
+            },
+            Operator::ShiftRight => match (l, r) {
+                (Value::Int(l_int), Value::Int(r_int)) => u32::try_from(r_int)
+                    .ok()
+                    .and_then(|r_int| l_int.checked_shr(r_int))
+                    .map(Value::Int)
+                    .ok_or_else(|| err_msg(format!("移位位数不对, {}", r_int))),
+                _ => Err(err_msg("不是 int 类型不能做位运算")),
+            },
             Operator::Equals => Ok(Value::Bool(l == r)),
             Operator::NotEquals => Ok(Value::Bool(l != r)),
-            Operator::NOT => unreachable!("到了这里就错了"),
-            Operator::Assign => unreachable!("到了这里就错了"),
+            Operator::NOT | Operator::BitNot | Operator::Coalesce => {
+                unreachable!("到了这里就错了")
+            }
+            Operator::Assign
+            | Operator::AddAssign
+            | Operator::SubtractAssign
+            | Operator::MultiplyAssign
+            | Operator::DivideAssign
+            | Operator::ModAssign => unreachable!("到了这里就错了"),
         }
     }
 }
 
+/// 整数运算的结果, 溢出时返回错误而不是 panic
+fn checked_int(res: Option<i64>, l: i64, operator: &str, r: i64) -> Result<Value> {
+    res.map(Value::Int)
+        .ok_or_else(|| err_msg(format!("整数运算溢出, {} {} {}", l, operator, r)))
+}
+
 /// 取反
 #[derive(Debug)]
 pub struct NotStatement {
@@ -138,8 +495,12 @@ pub struct NotStatement {
 }
 
 impl Expression for NotStatement {
+    fn node(&self) -> Node<'_> {
+        Node::Not(self)
+    }
+
     fn evaluate(&self, ctx: &mut Context) -> Result<Value> {
-        let res = self.expr.evaluate(ctx).unwrap();
+        let res = self.expr.evaluate(ctx)?;
         match res {
             Value::Bool(b) => Ok(Value::Bool(!b)),
             _ => Err(err_msg("逻辑运算符只能用在 bool 类型上")),
@@ -147,6 +508,48 @@ impl Expression for NotStatement {
     }
 }
 
+/// 按位取反
+#[derive(Debug)]
+pub struct BitNotStatement {
+    /// 要取反的表达式
+    pub expr: Box<dyn Expression>,
+}
+
+impl Expression for BitNotStatement {
+    fn node(&self) -> Node<'_> {
+        Node::BitNot(self)
+    }
+
+    fn evaluate(&self, ctx: &mut Context) -> Result<Value> {
+        match self.expr.evaluate(ctx)? {
+            Value::Int(i) => Ok(Value::Int(!i)),
+            _ => Err(err_msg("不是 int 类型不能做位运算")),
+        }
+    }
+}
+
+/// `left ?? right`, left 是 void 时才计算并返回 right, 否则返回 left
+#[derive(Debug)]
+pub struct CoalesceStatement {
+    /// 左边的表达式
+    pub left: Box<dyn Expression>,
+    /// 左边是 void 时使用的表达式
+    pub right: Box<dyn Expression>,
+}
+
+impl Expression for CoalesceStatement {
+    fn node(&self) -> Node<'_> {
+        Node::Coalesce(self)
+    }
+
+    fn evaluate(&self, ctx: &mut Context) -> Result<Value> {
+        match self.left.evaluate(ctx)? {
+            Value::Void => self.right.evaluate(ctx),
+            value => Ok(value),
+        }
+    }
+}
+
 /// 打印
 #[derive(Debug)]
 pub struct PrintStatement {
@@ -154,12 +557,18 @@ pub struct PrintStatement {
     pub expression: Box<dyn Expression>,
     /// 是否换行
     pub is_newline: bool,
+    /// 所在的行号
+    pub line: usize,
 }
 
 impl Expression for PrintStatement {
+    fn node(&self) -> Node<'_> {
+        Node::Print(self)
+    }
+
     fn evaluate(&self, ctx: &mut Context) -> Result<Value> {
-        let res = self.expression.evaluate(ctx).unwrap();
-        print!("{}", res.to_string());
+        let res = self.expression.evaluate(ctx)?;
+        print!("{}", res);
         if self.is_newline {
             println!();
         }
@@ -167,7 +576,9 @@ impl Expression for PrintStatement {
     }
 }
 
-/// 赋值语句
+/// 声明语句
+///
+/// 同一个作用域里重复声明同名变量是错误, 嵌套的作用域里可以声明同名变量遮蔽外层的变量
 #[derive(Debug)]
 pub struct DeclareStatement {
     /// 变量类型
@@ -176,16 +587,22 @@ pub struct DeclareStatement {
     pub left: String,
     /// 赋值语句右边的表达式
     pub right: Box<dyn Expression>,
+    /// 所在的行号
+    pub line: usize,
 }
 
 impl Expression for DeclareStatement {
+    fn node(&self) -> Node<'_> {
+        Node::Declare(self)
+    }
+
     fn evaluate(&self, ctx: &mut Context) -> Result<Value> {
         let res = self.right.evaluate(ctx)?;
         let is_ok = ctx.insert_var(self.left.as_str(), res, self.var_type.clone());
         if is_ok {
             Ok(Value::Void)
         } else {
-            Err(err_msg(format!("重复定义变量, {:?}", self)))
+            Err(err_msg(format!("重复定义变量 {}", self.left)))
         }
     }
 }
@@ -197,9 +614,15 @@ pub struct AssignStatement {
     pub left: String,
     /// 赋值语句右边的表达式
     pub right: Box<dyn Expression>,
+    /// 所在的行号
+    pub line: usize,
 }
 
 impl Expression for AssignStatement {
+    fn node(&self) -> Node<'_> {
+        Node::Assign(self)
+    }
+
     fn evaluate(&self, ctx: &mut Context) -> Result<Value> {
         let e = &self.right;
         let res = e.evaluate(ctx)?;
@@ -213,9 +636,17 @@ impl Expression for AssignStatement {
 }
 
 /// 一串表达式的集合
+///
+/// 语句块的值是最后一条语句的值, 空语句块的值是 `Value::Void`。
+/// `let`/`const` 声明、赋值和循环语句的值都是 `Value::Void`,
+/// 所以以它们结尾的语句块 (包括 if 分支和函数体) 的值也是 `Value::Void`。
 pub type BlockStatement = VecDeque<Box<dyn Expression>>;
 
 impl Expression for BlockStatement {
+    fn node(&self) -> Node<'_> {
+        Node::Block(self)
+    }
+
     fn evaluate(&self, ctx: &mut Context) -> Result<Value> {
         let mut new_ctx: Context = Context::init_with_parent_context(ctx);
         let mut res = Value::Void;
@@ -236,6 +667,10 @@ pub struct LoopStatement {
 }
 
 impl Expression for LoopStatement {
+    fn node(&self) -> Node<'_> {
+        Node::Loop(self)
+    }
+
     fn evaluate(&self, ctx: &mut Context) -> Result<Value> {
         let mut new_ctx: Context = Context::init_with_parent_context(ctx);
 
@@ -268,6 +703,10 @@ pub struct IfStatement {
 }
 
 impl Expression for IfStatement {
+    fn node(&self) -> Node<'_> {
+        Node::If(self)
+    }
+
     fn evaluate(&self, ctx: &mut Context) -> Result<Value> {
         let mut new_ctx: Context = Context::init_with_parent_context(ctx);
         let res = match self.predict.evaluate(&mut new_ctx)? {
@@ -292,13 +731,20 @@ pub enum Element {
 impl Debug for Element {
     fn fmt(&self, f: &mut Formatter) -> Result<(), std::fmt::Error> {
         match &self {
-            Element::Value(v) => v.fmt(f),
-            Element::Variable(v) => v.fmt(f),
+            Element::Value(v) => Debug::fmt(v, f),
+            Element::Variable(v) => Debug::fmt(v, f),
         }
     }
 }
 
 impl Expression for Element {
+    fn node(&self) -> Node<'_> {
+        match &self {
+            Element::Value(v) => v.node(),
+            Element::Variable(v) => v.node(),
+        }
+    }
+
     fn evaluate(&self, ctx: &mut Context) -> Result<Value> {
         match &self {
             Element::Value(v) => v.evaluate(ctx),
@@ -312,22 +758,35 @@ impl Expression for Element {
 pub struct VariableStatement {
     /// 变量名
     pub name: String,
+    /// 所在的行号
+    pub line: usize,
 }
 
 impl Expression for VariableStatement {
+    fn node(&self) -> Node<'_> {
+        Node::Variable(self)
+    }
+
     fn evaluate(&self, context: &mut Context) -> Result<Value> {
-        let val = context.get_var(&self.name);
-        assert!(val.is_some(), "不能获取一个未定义的变量 {}", self.name);
-        Ok(val.unwrap())
+        context
+            .get_var(&self.name)
+            .ok_or_else(|| err_msg(format!("不能获取一个未定义的变量 {}", self.name)))
     }
 }
 
 /// ----------------------------------------
 /// 常数类型
+///
+/// 值之间不会隐式转换, 只有下面这些规则:
+/// - 条件判断 (`if`、循环、`? :`) 和 `!` `&&` `||` 只接受 bool, 没有 "真值" 的概念, 其他类型都报错
+/// - `+` 两边有一个是 string 时, 另一边按 [`Display`] 转成字符串再拼接 (void 是空字符串);
+///   否则两边都必须是 int
+/// - 其他算术运算、位运算和 `>` `>=` `<` `<=` 两边都必须是 int
+/// - `==` `!=` 可以用在任意两个值上, 类型不同的值总是不相等, 例如 `1 != "1"`
 #[derive(PartialEq, Eq, Clone, Debug)]
 pub enum Value {
     /// int 常量
-    Int(i32),
+    Int(i64),
     /// bool 常量
     Bool(bool),
     /// void 常量
@@ -337,20 +796,105 @@ pub enum Value {
 }
 
 impl Expression for Value {
+    fn node(&self) -> Node<'_> {
+        Node::Value(self)
+    }
+
     fn evaluate(&self, _: &mut Context) -> Result<Value> {
         Ok(self.clone())
     }
 }
 
-impl ToString for Value {
-    fn to_string(&self) -> String {
+impl Value {
+    /// 类型名, `type(x)` 的返回值
+    pub fn type_name(&self) -> &'static str {
         match self {
-            Value::Int(int) => (*int).to_string(),
-            Value::Bool(b) => (*b).to_string(),
-            Value::Void => String::new(),
-            Value::Str(s) => s.clone(),
+            Value::Int(_) => "int",
+            Value::Bool(_) => "bool",
+            Value::Void => "void",
+            Value::Str(_) => "string",
+        }
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            Value::Int(int) => write!(f, "{}", int),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Void => Ok(()),
+            Value::Str(s) => write!(f, "{}", s),
             //            Value::Float(f) => f.to_string(),
         }
     }
 }
+
+impl From<i32> for Value {
+    fn from(value: i32) -> Self {
+        Value::Int(value.into())
+    }
+}
+
+impl From<i64> for Value {
+    fn from(value: i64) -> Self {
+        Value::Int(value)
+    }
+}
+
+impl From<bool> for Value {
+    fn from(value: bool) -> Self {
+        Value::Bool(value)
+    }
+}
+
+impl From<String> for Value {
+    fn from(value: String) -> Self {
+        Value::Str(value)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(value: &str) -> Self {
+        Value::Str(value.to_string())
+    }
+}
+
+impl From<()> for Value {
+    fn from(_: ()) -> Self {
+        Value::Void
+    }
+}
+
+impl TryFrom<Value> for i64 {
+    type Error = anyhow::Error;
+
+    fn try_from(value: Value) -> Result<Self> {
+        match value {
+            Value::Int(i) => Ok(i),
+            _ => Err(err_msg(format!("{:?} 不是 int", value))),
+        }
+    }
+}
+
+impl TryFrom<Value> for bool {
+    type Error = anyhow::Error;
+
+    fn try_from(value: Value) -> Result<Self> {
+        match value {
+            Value::Bool(b) => Ok(b),
+            _ => Err(err_msg(format!("{:?} 不是 bool", value))),
+        }
+    }
+}
+
+impl TryFrom<Value> for String {
+    type Error = anyhow::Error;
+
+    fn try_from(value: Value) -> Result<Self> {
+        match value {
+            Value::Str(s) => Ok(s),
+            _ => Err(err_msg(format!("{:?} 不是 string", value))),
+        }
+    }
+}
 //-----------------------------------------
@@ -8,6 +8,7 @@
 #![deny(unreachable_code)]
 
 use std::fmt::{Debug, Display};
+use std::time::{Duration, Instant};
 
 use anyhow::Result;
 use expression::Value;
@@ -24,10 +25,14 @@ use crate::context::Context;
 use crate::expression::*;
 use crate::token::*;
 
+/// 内置函数模块
+pub mod builtin;
 /// context模块
 pub mod context;
 /// 表达式模块
 pub mod expression;
+/// 代码检查模块
+pub mod lint;
 /// 语法分析模块
 pub mod parse;
 /// 测试模块
@@ -44,43 +49,213 @@ where
     anyhow::Error::msg(msg)
 }
 
+/// 入口函数的名字
+pub const MAIN_FUNCTION: &str = "main";
+
+/// 把源文件的字节转成源代码, 去掉开头的 UTF-8 BOM
+pub fn decode_source(bytes: Vec<u8>) -> Result<String> {
+    let mut code = String::from_utf8(bytes).map_err(|e| {
+        err_msg(format!(
+            "源文件不是有效的 UTF-8 编码, 位置: 第 {} 个字节",
+            e.utf8_error().valid_up_to() + 1
+        ))
+    })?;
+    if code.starts_with('\u{FEFF}') {
+        code.drain(..'\u{FEFF}'.len_utf8());
+    }
+    Ok(code)
+}
+
+/// 执行代码的线程的栈大小
+///
+/// 执行语法树是递归的, 每调用一层函数都要占用好几个 Rust 栈帧,
+/// 所以 [`run`]、[`run_main`]、[`run_timed`] 都在一个栈这么大的新线程里执行,
+/// 函数调用到 [`LARGE_STACK_MAX_CALL_DEPTH`] 层之前都不会栈溢出.
+/// wasm 上不能创建线程, 在当前线程里执行, 层数限制还是 [`DEFAULT_MAX_CALL_DEPTH`]
+pub const STACK_SIZE: usize = 256 << 20;
+
+/// 在栈大小为 [`STACK_SIZE`] 的线程里执行时, 函数调用的最大嵌套层数
+pub const LARGE_STACK_MAX_CALL_DEPTH: usize = 10_000;
+
+/// 在栈大小为 [`STACK_SIZE`] 的新线程里执行 `f`,
+/// 传给 `f` 的上下文允许函数调用嵌套 [`LARGE_STACK_MAX_CALL_DEPTH`] 层
+#[cfg(not(target_family = "wasm"))]
+fn with_stack<T, F>(f: F) -> Result<T>
+where
+    T: Send + 'static,
+    F: FnOnce(Context) -> Result<T> + Send + 'static,
+{
+    let handle = std::thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(|| {
+            let mut ctx = Context::default();
+            ctx.set_max_call_depth(LARGE_STACK_MAX_CALL_DEPTH);
+            f(ctx)
+        })?;
+    match handle.join() {
+        Ok(res) => res,
+        Err(panic) => std::panic::resume_unwind(panic),
+    }
+}
+
+/// wasm 不能创建线程, 直接在当前线程里用默认的上下文执行 `f`
+#[cfg(target_family = "wasm")]
+fn with_stack<T, F>(f: F) -> Result<T>
+where
+    F: FnOnce(Context) -> Result<T>,
+{
+    f(Context::default())
+}
+
+/// 在栈足够大的线程里分析并执行代码, 返回最后一条语句的值
+fn run_code(code: String, call_main: bool) -> Result<Value> {
+    with_stack(move |mut ctx| evaluate(&mut ctx, &compile(code)?, call_main))
+}
+
 /// 运行代码
 #[no_mangle]
 pub fn run(code: String) -> Result<()> {
-    let tokens = tokenlizer(code)?;
+    run_code(code, false)?;
+    Ok(())
+}
+
+/// 运行代码, 顶层语句执行完之后, 如果定义了 `main` 函数就再调用它
+pub fn run_main(code: String) -> Result<()> {
+    run_code(code, true)?;
+    Ok(())
+}
+
+/// 词法分析和语法分析, 得到的语法树可以用 [`execute`] 执行多次
+pub fn compile(code: String) -> Result<BlockStatement> {
+    let tokens = tokenlizer_with_line(code)?;
     debug!("tokens => {:?}", &tokens);
     let ast: BlockStatement = parser(tokens)?;
     debug!("ast => {:?}", &ast);
-    evaluate(ast)?;
-    Ok(())
+    Ok(ast)
+}
+
+/// 在一个新的上下文里执行语法树, 返回最后一条语句的值
+///
+/// 在当前线程里执行, 函数调用最多嵌套 [`DEFAULT_MAX_CALL_DEPTH`] 层,
+/// 需要更深的递归时, 在栈足够大的线程里用 [`execute_in`] 并调高 [`Context::set_max_call_depth`]
+pub fn execute(ast: &BlockStatement) -> Result<Value> {
+    evaluate(&mut Context::default(), ast, false)
+}
+
+/// 在给定的上下文里执行语法树, 返回最后一条语句的值
+///
+/// 可以先在上下文上做好设置, 比如 [`Context::set_max_call_depth`], 设置只对这一次执行有效,
+/// 和 [`execute`] 一样在当前线程里执行
+pub fn execute_in(ctx: &mut Context, ast: &BlockStatement) -> Result<Value> {
+    let mut res = Value::Void;
+    for cmd in ast.iter() {
+        res = cmd.evaluate(ctx)?;
+    }
+    Ok(res)
+}
+
+/// 各个阶段的耗时
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Timings {
+    /// 词法分析
+    pub tokenize: Duration,
+    /// 语法分析
+    pub parse: Duration,
+    /// 执行
+    pub execute: Duration,
+}
+
+/// 运行代码, 并记录词法分析、语法分析和执行分别用了多长时间
+pub fn run_timed(code: String, call_main: bool) -> Result<Timings> {
+    with_stack(move |mut ctx| timed(&mut ctx, code, call_main))
+}
+
+fn timed(ctx: &mut Context, code: String, call_main: bool) -> Result<Timings> {
+    let start = Instant::now();
+    let tokens = tokenlizer_with_line(code)?;
+    let tokenize = start.elapsed();
+
+    let start = Instant::now();
+    let ast = parser(tokens)?;
+    let parse = start.elapsed();
+
+    let start = Instant::now();
+    evaluate(ctx, &ast, call_main)?;
+    let execute = start.elapsed();
+
+    Ok(Timings {
+        tokenize,
+        parse,
+        execute,
+    })
 }
 
 /// 词法
-fn parser(tokens: Vec<Token>) -> Result<BlockStatement> {
-    let mut lines: Vec<Box<[Token]>> = vec![];
+///
+/// `tokens` 带着每个 token 所在的行号, 出错时用来提示是哪一行
+fn parser(tokens: Vec<(Token, usize)>) -> Result<BlockStatement> {
+    let mut lines: Vec<parse::Line> = vec![];
     let mut temp = vec![];
-    for x in tokens {
+    let mut number = 1;
+    let mut last_line = 1;
+    for (x, line) in tokens {
+        last_line = line;
         if let Token::NewLine = x {
             if !temp.is_empty() {
-                lines.push(temp.into_boxed_slice());
+                lines.push(parse::Line {
+                    number,
+                    tokens: temp.into_boxed_slice(),
+                });
                 temp = vec![];
             }
         } else {
+            if temp.is_empty() {
+                number = line;
+            }
             temp.push(x)
         }
     }
-    let (_, ast) = parse::parse_block(lines.as_slice(), 0)?;
+    // 最后一行可以没有换行符
+    if !temp.is_empty() {
+        lines.push(parse::Line {
+            number,
+            tokens: temp.into_boxed_slice(),
+        });
+    }
+    let (end_line, ast) = parse::parse_block(lines.as_slice(), 0).map_err(|err| {
+        if let Some(block) = err.downcast_ref::<parse::UnterminatedBlock>() {
+            return err_msg(format!(
+                "第 {} 行开始的语句块没有结束, 到第 {} 行文件结束都没有找到对应的 `}}`",
+                lines[block.start].number, last_line
+            ));
+        }
+        if let Some(assign) = err.downcast_ref::<parse::AssignInExpression>() {
+            if let Some(line) = assign.line {
+                return err_msg(format!("第 {} 行: {}", lines[line].number, assign));
+            }
+        }
+        err
+    })?;
+    if end_line < lines.len() {
+        return Err(err_msg(format!(
+            "第 {} 行有多余的 `}}`",
+            lines[end_line].number
+        )));
+    }
 
     Ok(ast)
 }
 
-/// 运行
-fn evaluate(ast: BlockStatement) -> Result<Value> {
-    let mut ctx = Context::default();
+/// 在给定的上下文里运行, 返回最后一条语句的值
+///
+/// `call_main` 为 true 时, 顶层语句执行完之后如果定义了 `main` 函数, 返回它的返回值
+fn evaluate(ctx: &mut Context, ast: &BlockStatement, call_main: bool) -> Result<Value> {
     debug!("{:?}", &ast);
-    for cmd in ast.iter() {
-        cmd.evaluate(&mut ctx)?;
+    let mut res = execute_in(ctx, ast)?;
+
+    if call_main && ctx.get_all_function().contains_key(MAIN_FUNCTION) {
+        res = call_function(ctx, MAIN_FUNCTION, vec![])?;
     }
 
-    Ok(Value::Void)
+    Ok(res)
 }
@@ -0,0 +1,193 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::expression::*;
+
+/// 检查语法树里常见的问题, 返回每个问题的描述, 格式是 `第 N 行: 问题`
+///
+/// 只做静态检查, 不执行代码:
+/// - 声明了但是没有用过的变量 (以 `_` 开头的变量除外)
+/// - 没有声明就使用或者赋值的变量
+/// - `return` 后面永远不会执行的语句
+/// - 在循环体里重新声明循环条件用到的变量
+pub fn lint(ast: &BlockStatement) -> Vec<String> {
+    let mut linter = Linter::default();
+    linter.visit_block(ast);
+    linter.warnings
+}
+
+/// 一层作用域里声明的变量, 值是 (有没有被用过, 声明所在的行号)
+type Scope = HashMap<String, (bool, usize)>;
+
+#[derive(Default)]
+struct Linter {
+    /// 作用域栈, 和执行时的 [`Context`](crate::context::Context) 一一对应
+    scopes: Vec<Scope>,
+    /// 外层循环的条件里用到的变量
+    loop_vars: Vec<HashSet<String>>,
+    warnings: Vec<String>,
+}
+
+impl Linter {
+    /// 在作用域栈里从内往外找变量, 找到了就标记为用过
+    fn mark_used(&mut self, name: &str) -> bool {
+        for scope in self.scopes.iter_mut().rev() {
+            if let Some((used, _)) = scope.get_mut(name) {
+                *used = true;
+                return true;
+            }
+        }
+        false
+    }
+
+    /// 作用域结束, 报告没有用过的变量
+    fn pop_scope(&mut self) {
+        if let Some(scope) = self.scopes.pop() {
+            let mut unused: Vec<_> = scope
+                .into_iter()
+                .filter(|(name, (used, _))| !used && !name.starts_with('_'))
+                .map(|(name, (_, line))| (line, name))
+                .collect();
+            unused.sort();
+            for (line, name) in unused {
+                self.warnings
+                    .push(format!("第 {} 行: 变量 {} 声明了但是没有使用", line, name));
+            }
+        }
+    }
+}
+
+impl Visitor for Linter {
+    fn visit_block(&mut self, block: &BlockStatement) {
+        self.scopes.push(Scope::new());
+        let mut returned = None;
+        for expr in block {
+            if let Some(return_line) = returned {
+                let mut lines = LineFinder::default();
+                lines.visit_expression(expr.as_ref());
+                self.warnings.push(match lines.first {
+                    Some(line) => format!("第 {} 行: return 后面的语句不会被执行", line),
+                    None => format!("第 {} 行的 return 后面的语句不会被执行", return_line),
+                });
+                break;
+            }
+            self.visit_expression(expr.as_ref());
+            if let Node::Return(stmt) = expr.node() {
+                returned = Some(stmt.line);
+            }
+        }
+        self.pop_scope();
+    }
+
+    fn visit_function(&mut self, func: &FunctionStatement) {
+        // 函数体里看不到外面的变量, 只有参数, 参数不会报告没有使用, 行号用不到
+        let scopes = std::mem::take(&mut self.scopes);
+        let loop_vars = std::mem::take(&mut self.loop_vars);
+        self.scopes.push(
+            func.params
+                .iter()
+                .map(|param| (param.clone(), (true, 0)))
+                .collect(),
+        );
+        self.visit_block(&func.body);
+        self.scopes = scopes;
+        self.loop_vars = loop_vars;
+    }
+
+    fn visit_declare(&mut self, stmt: &DeclareStatement) {
+        self.visit_expression(stmt.right.as_ref());
+        if self.loop_vars.iter().any(|vars| vars.contains(&stmt.left)) {
+            self.warnings.push(format!(
+                "第 {} 行: 循环变量 {} 在循环体里被重新声明, 循环条件用的还是外面的 {}",
+                stmt.line, stmt.left, stmt.left
+            ));
+        }
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(stmt.left.clone(), (false, stmt.line));
+        }
+    }
+
+    fn visit_assign(&mut self, stmt: &AssignStatement) {
+        self.visit_expression(stmt.right.as_ref());
+        // 只赋值不算使用, 所以不能用 mark_used
+        let defined = self.scopes.iter().any(|it| it.contains_key(&stmt.left));
+        if !defined {
+            self.warnings.push(format!(
+                "第 {} 行: 给没有声明的变量 {} 赋值",
+                stmt.line, stmt.left
+            ));
+        }
+    }
+
+    fn visit_loop(&mut self, stmt: &LoopStatement) {
+        self.visit_expression(stmt.predict.as_ref());
+        let mut vars = VariableCollector::default();
+        vars.visit_expression(stmt.predict.as_ref());
+        self.loop_vars.push(vars.names);
+        self.visit_block(&stmt.loop_block);
+        self.loop_vars.pop();
+    }
+
+    fn visit_variable(&mut self, var: &VariableStatement) {
+        if !self.mark_used(&var.name) {
+            self.warnings.push(format!(
+                "第 {} 行: 使用了没有声明的变量 {}",
+                var.line, var.name
+            ));
+        }
+    }
+}
+
+/// 收集表达式里用到的变量名
+#[derive(Default)]
+struct VariableCollector {
+    names: HashSet<String>,
+}
+
+impl Visitor for VariableCollector {
+    fn visit_variable(&mut self, var: &VariableStatement) {
+        self.names.insert(var.name.clone());
+    }
+}
+
+/// 找出表达式里最靠前的行号, 有的节点 (比如常量) 没有记录行号
+#[derive(Default)]
+struct LineFinder {
+    first: Option<usize>,
+}
+
+impl LineFinder {
+    fn found(&mut self, line: usize) {
+        self.first = Some(self.first.map_or(line, |first| first.min(line)));
+    }
+}
+
+impl Visitor for LineFinder {
+    fn visit_call(&mut self, call: &CallFunctionStatement) {
+        self.found(call.line);
+        walk_call(self, call);
+    }
+
+    fn visit_return(&mut self, stmt: &ReturnStatement) {
+        self.found(stmt.line);
+        self.visit_expression(stmt.expr.as_ref());
+    }
+
+    fn visit_print(&mut self, stmt: &PrintStatement) {
+        self.found(stmt.line);
+        self.visit_expression(stmt.expression.as_ref());
+    }
+
+    fn visit_declare(&mut self, stmt: &DeclareStatement) {
+        self.found(stmt.line);
+        self.visit_expression(stmt.right.as_ref());
+    }
+
+    fn visit_assign(&mut self, stmt: &AssignStatement) {
+        self.found(stmt.line);
+        self.visit_expression(stmt.right.as_ref());
+    }
+
+    fn visit_variable(&mut self, var: &VariableStatement) {
+        self.found(var.line);
+    }
+}
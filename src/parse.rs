@@ -2,6 +2,7 @@
 
 use std::cmp::Ordering;
 use std::collections::VecDeque;
+use std::ops::Deref;
 use std::rc::Rc;
 use std::vec;
 
@@ -12,21 +13,53 @@ use crate::context::VarType;
 use crate::parse::OperatorPriority::*;
 use crate::*;
 
+/// 运算符优先级, 从高到低排列
 #[derive(Debug, Eq, PartialEq, Clone)]
 enum OperatorPriority {
-    Middle,
-    Small,
-    Normal,
-    Minimal,
+    /// ~
+    Unary,
+    /// **
+    Power,
+    /// * / %
+    Multiplicative,
+    /// + -
+    Additive,
+    /// << >>
+    Shift,
+    /// &
+    BitAnd,
+    /// ^
+    BitXor,
+    /// |
+    BitOr,
+    /// == != > < >= <=
+    Comparison,
+    /// !
+    Not,
+    /// = += -= *= /= %=
+    Assignment,
+    /// && ||
+    Logical,
+    /// ??
+    Coalesce,
 }
 
 impl OperatorPriority {
     fn priority_value(&self) -> i32 {
         match self {
-            Middle => 2,
-            Small => 1,
-            Normal => 0,
-            Minimal => -1,
+            Unary => 9,
+            Power => 8,
+            Multiplicative => 7,
+            Additive => 6,
+            Shift => 5,
+            BitAnd => 4,
+            BitXor => 3,
+            BitOr => 2,
+            Comparison => 1,
+            Not => 0,
+            Assignment => 0,
+            Logical => -1,
+            Coalesce => -2,
         }
     }
 }
@@ -39,236 +72,410 @@ impl PartialOrd for OperatorPriority {
 
 fn get_priority(opt: &Operator) -> OperatorPriority {
     match opt {
-        Operator::ADD => Small,
-        Operator::Subtract => Small,
-        Operator::Multiply => Middle,
-        Operator::Divide => Middle,
-        Operator::Mod => Middle,
-        Operator::Assign => Small,
-        Operator::And => Minimal,
-        Operator::Equals => Middle,
-        Operator::NotEquals => Middle,
-        Operator::Or => Minimal,
-        Operator::NOT => Normal,
-        Operator::GT => Middle,
-        Operator::LT => Middle,
-        Operator::GTE => Middle,
-        Operator::LTE => Middle,
+        Operator::ADD => Additive,
+        Operator::Subtract => Additive,
+        Operator::Multiply => Multiplicative,
+        Operator::Divide => Multiplicative,
+        Operator::Mod => Multiplicative,
+        Operator::Power => Power,
+        Operator::Assign => Assignment,
+        Operator::AddAssign => Assignment,
+        Operator::SubtractAssign => Assignment,
+        Operator::MultiplyAssign => Assignment,
+        Operator::DivideAssign => Assignment,
+        Operator::ModAssign => Assignment,
+        Operator::And => Logical,
+        Operator::Equals => Comparison,
+        Operator::NotEquals => Comparison,
+        Operator::Or => Logical,
+        Operator::NOT => Not,
+        Operator::GT => Comparison,
+        Operator::LT => Comparison,
+        Operator::GTE => Comparison,
+        Operator::LTE => Comparison,
+        Operator::BitAnd => BitAnd,
+        Operator::BitOr => BitOr,
+        Operator::BitXor => BitXor,
+        Operator::ShiftLeft => Shift,
+        Operator::ShiftRight => Shift,
+        Operator::BitNot => Unary,
+        Operator::Coalesce => Coalesce,
     }
 }
 
-/// 简单表达式分析 (只有运算的 一行)
-pub fn parse_expression(line: &[Token]) -> Result<Box<dyn Expression>> {
+/// 一行代码的 token, 带着这一行在源代码里的行号
+#[derive(Debug)]
+pub struct Line {
+    /// 行号, 从 1 开始
+    pub number: usize,
+    /// 这一行的 token, 不包括换行
+    pub tokens: Box<[Token]>,
+}
+
+impl Deref for Line {
+    type Target = [Token];
+
+    fn deref(&self) -> &[Token] {
+        &self.tokens
+    }
+}
+
+/// 后缀表达式中的一项
+enum Postfix {
+    /// 操作符
+    Operator(Operator),
+    /// 操作数
+    Operand(Box<dyn Expression>),
+}
+
+/// 简单表达式分析 (只有运算的 一行), `line_number` 是表达式所在的行号
+pub fn parse_expression(line: &[Token], line_number: usize) -> Result<Box<dyn Expression>> {
     if line.is_empty() {
         return Ok(Box::new(Value::Void));
     }
 
+    // 三元表达式的优先级最低
+    if let Some((question, colon)) = find_ternary(line)? {
+        return Ok(Box::new(IfStatement {
+            predict: parse_expression(&line[..question], line_number)?,
+            if_block: VecDeque::from([parse_expression(
+                &line[(question + 1)..colon],
+                line_number,
+            )?]),
+            else_block: VecDeque::from([parse_expression(&line[(colon + 1)..], line_number)?]),
+        }));
+    }
+
     // 中缀表达式变后缀表达式
-    let mut result: Vec<&Token> = Vec::new();
-    let mut stack: Vec<&Token> = vec![];
-    for token in line {
-        match *token {
-            Token::LParen => stack.push(token),
-            Token::RParen => {
-                while let Some(top) = stack.pop() {
-                    if *top == Token::LParen {
-                        break;
-                    }
-                    result.push(top);
-                }
+    let mut result: Vec<Postfix> = Vec::new();
+    let mut stack: Vec<Operator> = vec![];
+    let mut idx = 0;
+    while idx < line.len() {
+        let token = &line[idx];
+        match token {
+            // 括号里面的部分作为一个整体
+            Token::LParen => {
+                let end = find_right_paren(line, idx)?;
+                result.push(Postfix::Operand(parse_expression(
+                    &line[(idx + 1)..end],
+                    line_number,
+                )?));
+                idx = end;
             }
+            // 赋值只能是单独的一条语句
+            Token::Operator(Operator::Assign) => {
+                return Err(AssignInExpression { line: None }.into());
+            }
+            Token::Operator(opt) if opt.is_assign() => {
+                return Err(err_msg(format!(
+                    "表达式里不能用 {:?} 赋值, {:?}",
+                    opt, line
+                )));
+            }
+            // 前缀运算符还没有操作数, 不能让栈里的运算符出栈
+            Token::Operator(opt @ (Operator::NOT | Operator::BitNot)) => stack.push(*opt),
             Token::Operator(opt) => {
-                while let Some(Token::Operator(opt2)) = stack.last() {
-                    if get_priority(opt2) >= get_priority(&opt) {
-                        result.push(stack.pop().unwrap());
+                while let Some(opt2) = stack.last() {
+                    // `**` 是右结合的, 优先级相同时不出栈
+                    let pop = match opt {
+                        Operator::Power => get_priority(opt2) > get_priority(opt),
+                        _ => get_priority(opt2) >= get_priority(opt),
+                    };
+                    if pop {
+                        result.push(Postfix::Operator(*opt2));
+                        stack.pop();
                         continue;
                     }
                     break;
                 }
-                stack.push(token);
+                stack.push(*opt);
+            }
+            // 函数调用
+            Token::Identifier(_) if line.get(idx + 1) == Some(&Token::LParen) => {
+                let end = find_right_paren(line, idx + 1)?;
+                result.push(Postfix::Operand(parse_func_call(
+                    &line[idx..=end],
+                    line_number,
+                )?));
+                idx = end;
             }
-            _ => result.push(token),
+            _ => result.push(Postfix::Operand(parse_operand(token, line_number)?)),
         }
+        idx += 1;
     }
-    while let Some(t) = stack.pop() {
-        result.push(t);
+    while let Some(opt) = stack.pop() {
+        result.push(Postfix::Operator(opt));
     }
 
-    let mut result: VecDeque<_> = result
-        .into_iter()
-        .filter(|&it| it != &Token::LParen && it != &Token::RParen)
-        .cloned()
-        .collect();
+    let mut tmp: Vec<Box<dyn Expression>> = Vec::new();
+    let pop_operand = |tmp: &mut Vec<Box<dyn Expression>>| {
+        tmp.pop()
+            .ok_or_else(|| err_msg(format!("表达式语法不对, {:?}", line)))
+    };
 
-    let mut tmp: VecDeque<Box<dyn Expression>> = VecDeque::new();
+    for item in result {
+        let new_exp: Box<dyn Expression> = match item {
+            Postfix::Operand(expr) => expr,
+            Postfix::Operator(Operator::NOT) => Box::new(NotStatement {
+                expr: pop_operand(&mut tmp)?,
+            }),
+            Postfix::Operator(Operator::BitNot) => Box::new(BitNotStatement {
+                expr: pop_operand(&mut tmp)?,
+            }),
+            Postfix::Operator(Operator::Coalesce) => {
+                let right = pop_operand(&mut tmp)?;
+                let left = pop_operand(&mut tmp)?;
+                Box::new(CoalesceStatement { left, right })
+            }
+            Postfix::Operator(opt) => {
+                let o1 = pop_operand(&mut tmp)?;
+                let o2 = pop_operand(&mut tmp)?;
+                Box::new(BinaryStatement {
+                    left: o2,
+                    right: o1,
+                    operator: opt,
+                })
+            }
+        };
+        tmp.push(new_exp);
+    }
 
-    while let Some(t) = result.pop_front() {
-        if let Token::Operator(opt) = t {
-            let new_exp: Box<dyn Expression> = match opt {
-                Operator::Assign => {
-                    unreachable!();
-                }
+    match tmp.len() {
+        1 => pop_operand(&mut tmp),
+        _ => Err(err_msg(format!("表达式语法不对, {:?}", line))),
+    }
+}
 
-                Operator::NOT => Box::new(NotStatement {
-                    expr: tmp.pop_back().unwrap(),
-                }),
+/// 分析表达式里的操作数
+fn parse_operand(token: &Token, line_number: usize) -> Result<Box<dyn Expression>> {
+    let ele: Element = match token {
+        Token::Identifier(name) => Element::Variable(VariableStatement {
+            name: name.clone(),
+            line: line_number,
+        }),
+        Token::Int(i) => Element::Value(Value::Int(*i)),
+        Token::Bool(i) => Element::Value(Value::Bool(*i)),
+        Token::String(i) => Element::Value(Value::Str(i.clone())),
+        Token::Template(parts) => return parse_template(parts, line_number),
+        _ => return Err(err_msg(format!("表达式里不能有 {:?}", token))),
+    };
+    Ok(Box::new(ele))
+}
 
-                _ => {
-                    let o1 = tmp.pop_back().unwrap();
-                    let o2 = tmp.pop_back().unwrap();
-                    Box::new(BinaryStatement {
-                        left: o2,
-                        right: o1,
-                        operator: opt,
-                    })
-                }
-            };
-            tmp.push_back(new_exp);
-        } else {
-            let ele: Element = match t {
-                Token::Identifier(name) => Element::Variable(VariableStatement { name }),
-                Token::Int(i) => Element::Value(Value::Int(i)),
-                Token::Bool(i) => Element::Value(Value::Bool(i)),
-                Token::String(i) => Element::Value(Value::Str(i)),
-                _ => panic!("错误,{:?}", t),
-            };
-            tmp.push_back(Box::new(ele));
+/// 找到最外层三元表达式 `cond ? a : b` 的 `?` 和 `:` 的位置
+///
+/// 取第一个 `?`, 所以 `a ? b : c ? d : e` 是右结合的
+fn find_ternary(line: &[Token]) -> Result<Option<(usize, usize)>> {
+    let mut depth = 0;
+    let mut question = None;
+    let mut nested = 0;
+    for (idx, token) in line.iter().enumerate() {
+        match token {
+            Token::LParen => depth += 1,
+            Token::RParen => depth -= 1,
+            Token::QUESTION if depth == 0 && question.is_none() => question = Some(idx),
+            Token::QUESTION if depth == 0 => nested += 1,
+            Token::COLON if depth == 0 && nested > 0 => nested -= 1,
+            Token::COLON if depth == 0 => match question {
+                Some(question) => return Ok(Some((question, idx))),
+                None => break,
+            },
+            _ => {}
         }
     }
+    match question {
+        Some(_) => Err(err_msg(format!("三元表达式缺少 `:`, {:?}", line))),
+        None => Ok(None),
+    }
+}
+
+/// 找到和 `start` 位置的左括号匹配的右括号
+fn find_right_paren(line: &[Token], start: usize) -> Result<usize> {
+    let mut depth = 0;
+    for (idx, token) in line.iter().enumerate().skip(start) {
+        match token {
+            Token::LParen => depth += 1,
+            Token::RParen if depth == 1 => return Ok(idx),
+            Token::RParen => depth -= 1,
+            _ => {}
+        }
+    }
+    Err(err_msg(format!("括号不匹配, {:?}", line)))
+}
+
+/// 字符串模板转成字符串拼接, `"a${b}c"` 等价于 `"" + "a" + (b) + "c"`
+fn parse_template(parts: &[TemplatePart], line_number: usize) -> Result<Box<dyn Expression>> {
+    let mut expr: Box<dyn Expression> = Box::new(Value::Str(String::new()));
+    for part in parts {
+        let right: Box<dyn Expression> = match part {
+            TemplatePart::Str(s) => Box::new(Value::Str(s.clone())),
+            TemplatePart::Expr(tokens) => parse_expression(tokens, line_number)?,
+        };
+        expr = Box::new(BinaryStatement {
+            left: expr,
+            right,
+            operator: Operator::ADD,
+        });
+    }
+    Ok(expr)
+}
+
+/// 语句块没有以 `}` 结束, 一直到了文件末尾
+#[derive(Debug, thiserror::Error)]
+#[error("语句块没有结束, 缺少 `}}`")]
+pub struct UnterminatedBlock {
+    /// `{` 所在的行在 lines 里的下标
+    pub start: usize,
+}
+
+/// 表达式里用了 `=`, 多半是想写 `==`
+#[derive(Debug, thiserror::Error)]
+#[error("表达式里不能用 `=` 赋值, 是不是想写 `==`?")]
+pub struct AssignInExpression {
+    /// 所在的行在 lines 里的下标, 由 [`parse_block`] 填上
+    pub line: Option<usize>,
+}
 
-    Ok(Box::new(tmp))
+/// 分析 `{` 后面的语句块, `open_line` 是 `{` 所在的行, 返回 `}` 所在的行
+fn parse_body(lines: &[Line], open_line: usize) -> Result<(usize, BlockStatement)> {
+    let (end_line, block) = parse_block(lines, open_line + 1)?;
+    if end_line >= lines.len() {
+        return Err(UnterminatedBlock { start: open_line }.into());
+    }
+    Ok((end_line, block))
 }
 
 /// 分析很多行的方法
-pub fn parse_block(
-    lines: &[Box<[Token]>],
-    mut start_line: usize,
-) -> Result<(usize, BlockStatement)> {
+pub fn parse_block(lines: &[Line], mut start_line: usize) -> Result<(usize, BlockStatement)> {
     let mut v = VecDeque::new();
     while start_line < lines.len() && lines[start_line][0] != Token::RBig {
-        match &lines[start_line][0] {
-            Token::Keyword(Keyword::LET) | Token::Keyword(Keyword::CONST) => {
-                let var = parse_declare(&lines[start_line])?;
-                v.push_back(var);
-                start_line += 1;
-            }
-            Token::Keyword(Keyword::FOR) => {
-                let var = parse_for(lines, start_line)?;
-                v.push_back(var.1);
-                start_line = var.0 + 1;
-            }
-            Token::Keyword(Keyword::DEF) => {
-                let var = parse_define_function(lines, start_line)?;
-                v.push_back(var.1);
-                start_line = var.0 + 1;
+        let (end_line, var) = parse_statement(lines, start_line).map_err(|mut err| {
+            if let Some(assign) = err.downcast_mut::<AssignInExpression>() {
+                assign.line.get_or_insert(start_line);
             }
-            Token::Keyword(Keyword::IF) => {
-                let var = parse_if(lines, start_line)?;
-                v.push_back(var.1);
-                start_line = var.0 + 1;
-            }
-            Token::StdFunction(StdFunction::Print(is_newline)) => {
-                let var = parse_print(&lines[start_line], *is_newline)?;
-                v.push_back(var);
-                start_line += 1;
-            }
-            // 赋值
-            Token::Identifier(_)
-                if lines[start_line].get(1) == Some(&Token::Operator(Operator::Assign)) =>
-            {
-                let var = parse_assign(&lines[start_line])?;
-                v.push_back(var);
-                start_line += 1;
-            }
-            // 函数调用
-            Token::Identifier(_) if lines[start_line].get(1) == Some(&Token::LParen) => {
-                let var = parse_func_call(&lines[start_line])?;
-                v.push_back(var);
-                start_line += 1;
-            }
-            Token::LBig => {
-                let var = parse_block(lines, start_line + 1)?;
-                v.push_back(Box::new(var.1));
-                start_line += var.0 + 1;
-            }
-            // 返回值
-            Token::Identifier(_) if lines[start_line].get(1).is_none() => {
-                let var = parse_expression(&lines[start_line])?;
-                v.push_back(var);
-                start_line += 1;
-            }
-            // 返回值
-            Token::Int(_) | Token::Bool(_) if lines[start_line].get(1).is_none() => {
-                let var = parse_expression(&lines[start_line])?;
-                v.push_back(var);
-                start_line += 1;
-            }
-            _ => {
-                unimplemented!("{:?}", lines[start_line]);
-            }
-        }
+            err
+        })?;
+        v.push_back(var);
+        start_line = end_line + 1;
     }
     Ok((start_line, v))
 }
 
-fn parse_func_call(line: &[Token]) -> Result<Box<dyn Expression>> {
-    let func_name = if let Token::Identifier(name) = &line[0] {
-        name.to_string()
-    } else {
-        return Err(err_msg("不是函数定义语句"));
-    };
-
-    assert_eq!(&line[1], &Token::LParen);
-    let param_idx: Vec<_> = line
-        .iter()
-        .enumerate()
-        .skip(2)
-        .filter(|it| it.1 == &Token::COMMA)
-        .map(|it| it.0)
-        .collect();
-
-    let mut params = vec![];
-
-    match param_idx.len() {
-        0 => {
-            params.push(parse_expression(&line[2..(line.len() - 1)])?);
+/// 分析从 `start_line` 开始的一条语句, 返回语句最后一行
+fn parse_statement(lines: &[Line], start_line: usize) -> Result<(usize, Box<dyn Expression>)> {
+    let line = &lines[start_line];
+    match &line[0] {
+        Token::Keyword(Keyword::LET) | Token::Keyword(Keyword::CONST) => {
+            Ok((start_line, parse_declare(line, line.number)?))
         }
-        _ => {
-            params.push(parse_expression(&line[2..param_idx[0]])?);
-            for i in 0..(param_idx.len() - 1) {
-                params.push(parse_expression(&line[param_idx[i]..param_idx[i + 1]])?);
-            }
-            params.push(parse_expression(
-                &line[(param_idx[param_idx.len() - 1] + 1)..(line.len() - 1)],
-            )?);
+        Token::Keyword(Keyword::FOR) | Token::Keyword(Keyword::WHILE) => {
+            parse_for(lines, start_line)
+        }
+        Token::Keyword(Keyword::DEF) => parse_define_function(lines, start_line),
+        Token::Keyword(Keyword::IF) => parse_if(lines, start_line),
+        Token::Keyword(Keyword::RETURN) => {
+            let var = ReturnStatement {
+                expr: parse_expression(&line[1..], line.number)?,
+                line: line.number,
+            };
+            Ok((start_line, Box::new(var)))
         }
+        Token::StdFunction(StdFunction::Print(is_newline)) => {
+            Ok((start_line, parse_print(line, *is_newline, line.number)?))
+        }
+        // 赋值
+        Token::Identifier(_) if is_assign_operator(line.get(1)) => {
+            Ok((start_line, parse_assign(line, line.number)?))
+        }
+        Token::LBig => {
+            let (end_line, block) = parse_body(lines, start_line)?;
+            Ok((end_line, Box::new(block)))
+        }
+        // 表达式 (包括函数调用), 最后一个表达式的值是语句块的返回值
+        Token::Identifier(_)
+        | Token::Int(_)
+        | Token::Bool(_)
+        | Token::String(_)
+        | Token::Template(_)
+        | Token::LParen
+        | Token::Operator(Operator::NOT)
+        | Token::Operator(Operator::BitNot) => {
+            Ok((start_line, parse_expression(line, line.number)?))
+        }
+        _ => Err(err_msg(format!("不支持的语句, {:?}", line.tokens))),
     }
+}
+
+fn is_assign_operator(token: Option<&Token>) -> bool {
+    matches!(token, Some(Token::Operator(opt)) if opt.is_assign())
+}
+
+fn parse_func_call(line: &[Token], line_number: usize) -> Result<Box<dyn Expression>> {
+    let func_name = match line {
+        [Token::Identifier(name), Token::LParen, ..]
+            if find_right_paren(line, 1)? == line.len() - 1 =>
+        {
+            name.to_string()
+        }
+        _ => return Err(err_msg(format!("函数调用语法不对, {:?}", line))),
+    };
+
+    let params = split_params(&line[2..(line.len() - 1)])
+        .into_iter()
+        .map(|param| parse_expression(param, line_number))
+        .collect::<Result<Vec<_>>>()?;
 
     Ok(Box::new(CallFunctionStatement {
         function_name: func_name,
         params,
+        line: line_number,
     }))
 }
 
+/// 按最外层的逗号把参数分开
+fn split_params(tokens: &[Token]) -> Vec<&[Token]> {
+    if tokens.is_empty() {
+        return vec![];
+    }
+    let mut params = vec![];
+    let mut depth = 0;
+    let mut start = 0;
+    for (idx, token) in tokens.iter().enumerate() {
+        match token {
+            Token::LParen => depth += 1,
+            Token::RParen => depth -= 1,
+            Token::COMMA if depth == 0 => {
+                params.push(&tokens[start..idx]);
+                start = idx + 1;
+            }
+            _ => {}
+        }
+    }
+    params.push(&tokens[start..]);
+    params
+}
+
 /// 分析声明语句
-pub fn parse_declare(line: &[Token]) -> Result<Box<dyn Expression>> {
+pub fn parse_declare(line: &[Token], line_number: usize) -> Result<Box<dyn Expression>> {
     debug!("{:?}", &line);
 
-    let var_type = match &line[0] {
-        Token::Keyword(Keyword::LET) => VarType::Let,
-        Token::Keyword(Keyword::CONST) => VarType::Const,
-        _ => unreachable!(),
+    let syntax_error = || err_msg(format!("声明语句应该是 `let 变量名 = 表达式`, {:?}", line));
+    let var_type = match line.first() {
+        Some(Token::Keyword(Keyword::LET)) => VarType::Let,
+        Some(Token::Keyword(Keyword::CONST)) => VarType::Const,
+        _ => return Err(syntax_error()),
     };
 
-    let name = match &line[1] {
-        Token::Identifier(name) => name,
-        _ => unreachable!(),
+    let (name, right) = match &line[1..] {
+        [Token::Identifier(name), Token::Operator(Operator::Assign), right @ ..] => (name, right),
+        _ => return Err(syntax_error()),
     };
 
     let var = DeclareStatement {
         var_type,
         left: name.clone(),
-        right: parse_expression(&line[3..])?,
+        right: parse_expression(right, line_number)?,
+        line: line_number,
     };
     Ok(Box::new(var))
 }
@@ -284,7 +491,7 @@ pub fn parse_declare(line: &[Token]) -> Result<Box<dyn Expression>> {
 /// ```
 ///
 fn parse_define_function(
-    lines: &[Box<[Token]>],
+    lines: &[Line],
     start_line: usize,
 ) -> Result<(usize, Box<dyn Expression>)> {
     let func_name = if let Token::Identifier(name) = &lines[start_line][1] {
@@ -293,7 +500,7 @@ fn parse_define_function(
         return Err(err_msg("不是函数定义语句"));
     };
 
-    let (endline, body) = parse_block(lines, start_line + 1)?;
+    let (endline, body) = parse_body(lines, start_line)?;
 
     let params = lines[start_line]
         .iter()
@@ -313,25 +520,37 @@ fn parse_define_function(
 }
 
 /// 赋值语句分析
-pub fn parse_assign(line: &[Token]) -> Result<Box<dyn Expression>> {
+pub fn parse_assign(line: &[Token], line_number: usize) -> Result<Box<dyn Expression>> {
     debug!("{:?}", &line);
 
     match &line[0] {
         Token::Identifier(name) => {
-            assert_eq!(&line[1], &Token::Operator(Operator::Assign));
+            let operator = match &line[1] {
+                Token::Operator(opt) if opt.is_assign() => opt.compound_operator(),
+                _ => return Err(err_msg(format!("赋值语句语法不对，{:?}", line))),
+            };
 
             info!("{}:{} {:?}", file!(), line!(), &line);
 
-            let expr = match &line[2] {
-                Token::Identifier(_) if line.get(3) == Some(&Token::LParen) => {
-                    parse_func_call(&line[2..])?
-                }
-                _ => parse_expression(&line[2..])?,
+            let expr = parse_expression(&line[2..], line_number)?;
+
+            // 复合赋值 `a += b` 等价于 `a = a + b`
+            let expr = match operator {
+                Some(operator) => Box::new(BinaryStatement {
+                    left: Box::new(VariableStatement {
+                        name: name.clone(),
+                        line: line_number,
+                    }),
+                    right: expr,
+                    operator,
+                }),
+                None => expr,
             };
 
             let var = AssignStatement {
                 left: name.clone(),
                 right: expr,
+                line: line_number,
             };
             Ok(Box::new(var))
         }
@@ -340,43 +559,135 @@ pub fn parse_assign(line: &[Token]) -> Result<Box<dyn Expression>> {
 }
 
 /// 分析条件语句
-pub fn parse_if(lines: &[Box<[Token]>], start_line: usize) -> Result<(usize, Box<dyn Expression>)> {
-    let (mut endline, if_cmd) = parse_block(lines, start_line + 1)?;
+pub fn parse_if(lines: &[Line], start_line: usize) -> Result<(usize, Box<dyn Expression>)> {
+    let (mut endline, if_cmd) = parse_body(lines, start_line)?;
     let else_cmd = if let Some(Token::Keyword(Keyword::ELSE)) = lines[endline].get(1) {
         assert_eq!(lines[endline][0], Token::RBig);
         assert_eq!(lines[endline][2], Token::LBig);
-        let (new_endline, cmd) = parse_block(lines, endline + 1)?;
+        let (new_endline, cmd) = parse_body(lines, endline)?;
         endline = new_endline;
         cmd
     } else {
         VecDeque::new()
     };
     let loop_expr = IfStatement {
-        predict: parse_expression(&lines[start_line][1..(lines[start_line].len() - 1)])?,
+        predict: parse_expression(
+            &lines[start_line][1..(lines[start_line].len() - 1)],
+            lines[start_line].number,
+        )?,
         if_block: if_cmd,
         else_block: else_cmd,
     };
     Ok((endline, Box::new(loop_expr)))
 }
 
-/// 分析循环语句
-pub fn parse_for(
-    lines: &[Box<[Token]>],
-    start_line: usize,
-) -> Result<(usize, Box<dyn Expression>)> {
-    let cmd = parse_block(lines, start_line + 1)?;
-    let loop_expr = LoopStatement {
-        predict: parse_expression(&lines[start_line][1..(lines[start_line].len() - 1)])?,
-        loop_block: cmd.1,
+/// 分析循环语句, `for` 和 `while` 都由这里处理
+pub fn parse_for(lines: &[Line], start_line: usize) -> Result<(usize, Box<dyn Expression>)> {
+    let cmd = parse_body(lines, start_line)?;
+    let line_number = lines[start_line].number;
+    let header = match &lines[start_line][1..(lines[start_line].len() - 1)] {
+        [Token::LParen, inner @ .., Token::RParen] if inner.contains(&Token::SEMICOLON) => inner,
+        header => header,
     };
-    Ok((cmd.0, Box::new(loop_expr)))
+    let clauses: Vec<_> = header.split(|it| it == &Token::SEMICOLON).collect();
+    match clauses.as_slice() {
+        [predict] => {
+            let loop_expr = LoopStatement {
+                predict: parse_expression(predict, line_number)?,
+                loop_block: cmd.1,
+            };
+            Ok((cmd.0, Box::new(loop_expr)))
+        }
+        [init, predict, step] => {
+            let loop_expr = parse_c_style_for(init, predict, step, cmd.1, line_number)?;
+            Ok((cmd.0, loop_expr))
+        }
+        _ => Err(err_msg(format!("for 语句语法不对, {:?}", header))),
+    }
+}
+
+///
+/// ```java
+/// for let i = 0; i < 10; i = i + 1 {
+///
+/// }
+/// for (let i = 0; i < 10; i = i + 1) {
+///
+/// }
+/// ```
+///
+/// 转换成
+///
+/// ```java
+/// {
+///     let i = 0
+///     for i < 10 {
+///         {
+///
+///         }
+///         i = i + 1
+///     }
+/// }
+/// ```
+///
+/// 三个部分都可以省略, 省略条件时相当于 `true`
+fn parse_c_style_for(
+    init: &[Token],
+    predict: &[Token],
+    step: &[Token],
+    body: BlockStatement,
+    line_number: usize,
+) -> Result<Box<dyn Expression>> {
+    let predict: Box<dyn Expression> = if predict.is_empty() {
+        Box::new(Value::Bool(true))
+    } else {
+        parse_expression(predict, line_number)?
+    };
+
+    let mut loop_block: BlockStatement = VecDeque::new();
+    loop_block.push_back(Box::new(body));
+    if let Some(step) = parse_for_clause(step, line_number)? {
+        loop_block.push_back(step);
+    }
+
+    let mut block: BlockStatement = VecDeque::new();
+    if let Some(init) = parse_for_clause(init, line_number)? {
+        block.push_back(init);
+    }
+    block.push_back(Box::new(LoopStatement {
+        predict,
+        loop_block,
+    }));
+    Ok(Box::new(block))
+}
+
+/// 分析 for 语句的初始化和步进部分, 只能是声明或者赋值语句
+fn parse_for_clause(line: &[Token], line_number: usize) -> Result<Option<Box<dyn Expression>>> {
+    match line.first() {
+        None => Ok(None),
+        Some(Token::Keyword(Keyword::LET)) | Some(Token::Keyword(Keyword::CONST)) => {
+            Ok(Some(parse_declare(line, line_number)?))
+        }
+        Some(Token::Identifier(_)) if is_assign_operator(line.get(1)) => {
+            Ok(Some(parse_assign(line, line_number)?))
+        }
+        _ => Err(err_msg(format!(
+            "for 语句里只能是声明或赋值语句, {:?}",
+            line
+        ))),
+    }
 }
 
-fn parse_print(line: &[Token], is_newline: bool) -> Result<Box<dyn Expression>> {
+fn parse_print(
+    line: &[Token],
+    is_newline: bool,
+    line_number: usize,
+) -> Result<Box<dyn Expression>> {
     debug!("{:?}", line);
-    let expression = parse_expression(&line[2..(line.len() - 1)])?;
+    let expression = parse_expression(&line[2..(line.len() - 1)], line_number)?;
     Ok(Box::new(PrintStatement {
         expression,
         is_newline,
+        line: line_number,
     }))
 }
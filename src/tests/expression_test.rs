@@ -12,7 +12,7 @@ fn test_not_int2() {
     test_not_int(10);
 }
 
-fn test_not_int(i: i32) {
+fn test_not_int(i: i64) {
     let expr = NotStatement {
         expr: Box::new(Value(Int(i))),
     };
@@ -87,3 +87,225 @@ fn test_add_bool_int() {
     };
     opt.evaluate(&mut ctx).unwrap();
 }
+
+fn binary(operator: Operator, l: i64, r: i64) -> anyhow::Result<crate::expression::Value> {
+    let opt = BinaryStatement {
+        operator,
+        left: Box::new(Value(Int(l))),
+        right: Box::new(Value(Int(r))),
+    };
+    opt.evaluate(&mut Context::default())
+}
+
+#[test]
+fn test_bit_operators() {
+    assert_eq!(binary(Operator::BitAnd, 6, 3).unwrap(), Int(2));
+    assert_eq!(binary(Operator::BitOr, 6, 3).unwrap(), Int(7));
+    assert_eq!(binary(Operator::BitXor, 6, 3).unwrap(), Int(5));
+    assert_eq!(binary(Operator::ShiftLeft, 1, 4).unwrap(), Int(16));
+    assert_eq!(binary(Operator::ShiftRight, -16, 2).unwrap(), Int(-4));
+}
+
+#[test]
+fn test_shift_out_of_range() {
+    assert!(binary(Operator::ShiftLeft, 1, 64).is_err());
+    assert!(binary(Operator::ShiftRight, 1, -1).is_err());
+}
+
+#[test]
+fn test_bit_and_bool() {
+    let opt = BinaryStatement {
+        operator: Operator::BitAnd,
+        left: Box::new(Value(Bool(true))),
+        right: Box::new(Value(Int(1))),
+    };
+    assert!(opt.evaluate(&mut Context::default()).is_err());
+}
+
+#[test]
+fn test_int_overflow() {
+    assert!(binary(Operator::Multiply, 1 << 32, 1 << 32).is_err());
+    assert!(binary(Operator::ADD, i64::MAX, 1).is_err());
+    assert!(binary(Operator::Subtract, i64::MIN, 1).is_err());
+    assert!(binary(Operator::Divide, i64::MIN, -1).is_err());
+    assert!(binary(Operator::Mod, i64::MIN, -1).is_err());
+    assert_eq!(
+        binary(Operator::Multiply, 100000, 100000).unwrap(),
+        Int(10000000000)
+    );
+}
+
+#[test]
+fn test_divide_by_zero() {
+    assert!(binary(Operator::Divide, 1, 0).is_err());
+    assert!(binary(Operator::Mod, 1, 0).is_err());
+}
+
+#[test]
+fn test_power() {
+    assert_eq!(binary(Operator::Power, 2, 10).unwrap(), Int(1024));
+    assert_eq!(binary(Operator::Power, 7, 0).unwrap(), Int(1));
+    assert_eq!(
+        binary(Operator::Power, 2, -1).unwrap_err().to_string(),
+        "指数不能是负数, -1"
+    );
+    assert!(binary(Operator::Power, 2, 63).is_err());
+}
+
+#[test]
+fn test_value_from() {
+    use crate::expression::Value;
+
+    assert_eq!(Value::from(1i32), Int(1));
+    assert_eq!(Value::from(i64::MAX), Int(i64::MAX));
+    assert_eq!(Value::from(true), Bool(true));
+    assert_eq!(Value::from("a"), Str("a".to_string()));
+    assert_eq!(Value::from("b".to_string()), Str("b".to_string()));
+    assert_eq!(Value::from(()), Value::Void);
+}
+
+#[test]
+fn test_value_try_into() {
+    let i: i64 = Int(1).try_into().unwrap();
+    assert_eq!(i, 1);
+    let b: bool = Bool(false).try_into().unwrap();
+    assert!(!b);
+    let s: String = Str("a".to_string()).try_into().unwrap();
+    assert_eq!(s, "a");
+
+    let err = i64::try_from(Str("1".to_string())).unwrap_err();
+    assert_eq!(err.to_string(), r#"Str("1") 不是 int"#);
+}
+
+#[test]
+fn test_call_function() {
+    use std::rc::Rc;
+
+    use crate::compile;
+    use crate::expression::{call_function, FunctionStatement};
+
+    let mut ctx = Context::default();
+    ctx.insert_function(
+        "inc",
+        FunctionStatement {
+            name: "inc".to_string(),
+            params: vec!["a".to_string()],
+            body: Rc::new(compile("a + 1".to_string()).unwrap()),
+        },
+    );
+    assert_eq!(call_function(&ctx, "inc", vec![Int(1)]).unwrap(), Int(2));
+    assert_eq!(call_function(&ctx, "abs", vec![Int(-1)]).unwrap(), Int(1));
+    let err = call_function(&ctx, "dec", vec![Int(1)]).unwrap_err();
+    assert_eq!(err.to_string(), "调用了一个不存在的函数, dec");
+}
+
+#[test]
+fn test_visitor() {
+    use crate::compile;
+    use crate::expression::{walk_block, FunctionStatement, VariableStatement, Visitor};
+
+    /// 统计函数定义的个数, 顺便记录用到的变量
+    #[derive(Default)]
+    struct Counter {
+        functions: Vec<String>,
+        variables: Vec<String>,
+    }
+
+    impl Visitor for Counter {
+        fn visit_function(&mut self, func: &FunctionStatement) {
+            self.functions.push(func.name.clone());
+            walk_block(self, &func.body);
+        }
+
+        fn visit_variable(&mut self, var: &VariableStatement) {
+            self.variables.push(var.name.clone());
+        }
+    }
+
+    let ast = compile(
+        r#"
+def outer(a) {
+    def inner(b) {
+        return b * 2
+    }
+    if a > 0 {
+        inner(a)
+    }
+}
+for let i = 0; i < 3; i += 1 {
+    println(outer(i))
+}
+"#
+        .to_string(),
+    )
+    .unwrap();
+
+    let mut counter = Counter::default();
+    counter.visit_block(&ast);
+    assert_eq!(counter.functions, vec!["outer", "inner"]);
+    assert_eq!(counter.variables, vec!["b", "a", "a", "i", "i", "i"]);
+}
+
+#[test]
+fn test_coercion_table() {
+    use crate::expression::{IfStatement, Value as V};
+
+    let int = || Int(1);
+    let bool = || Bool(true);
+    let str = || Str("s".to_string());
+    let void = || V::Void;
+
+    #[rustfmt::skip]
+    let binary_cases: Vec<(Operator, V, V, Option<V>)> = vec![
+        // `+` 有 string 时拼接
+        (Operator::ADD, int(), int(), Some(Int(2))),
+        (Operator::ADD, str(), int(), Some(Str("s1".to_string()))),
+        (Operator::ADD, bool(), str(), Some(Str("trues".to_string()))),
+        (Operator::ADD, str(), void(), Some(Str("s".to_string()))),
+        (Operator::ADD, int(), bool(), None),
+        (Operator::ADD, void(), int(), None),
+        // 其他算术运算只接受 int
+        (Operator::Subtract, int(), int(), Some(Int(0))),
+        (Operator::Multiply, str(), int(), None),
+        (Operator::BitAnd, bool(), bool(), None),
+        // 比较大小只接受 int
+        (Operator::LT, int(), int(), Some(Bool(false))),
+        (Operator::LT, str(), str(), None),
+        (Operator::GT, bool(), int(), None),
+        // 相等比较不转换类型
+        (Operator::Equals, int(), int(), Some(Bool(true))),
+        (Operator::Equals, int(), Str("1".to_string()), Some(Bool(false))),
+        (Operator::Equals, void(), void(), Some(Bool(true))),
+        (Operator::NotEquals, bool(), int(), Some(Bool(true))),
+        // 逻辑运算只接受 bool
+        (Operator::And, bool(), bool(), Some(Bool(true))),
+        (Operator::Or, int(), bool(), None),
+    ];
+    for (operator, l, r, expected) in binary_cases {
+        let expr = BinaryStatement {
+            operator,
+            left: Box::new(Value(l.clone())),
+            right: Box::new(Value(r.clone())),
+        };
+        let res = expr.evaluate(&mut Context::default()).ok();
+        assert_eq!(res, expected, "{:?} {:?} {:?}", l, operator, r);
+    }
+
+    // 条件判断只接受 bool
+    let truthy_cases = vec![
+        (bool(), Some(Int(1))),
+        (Bool(false), Some(Int(0))),
+        (int(), None),
+        (str(), None),
+        (void(), None),
+    ];
+    for (predict, expected) in truthy_cases {
+        let expr = IfStatement {
+            predict: Box::new(Value(predict.clone())),
+            if_block: [Box::new(Value(Int(1))) as Box<dyn Expression>].into(),
+            else_block: [Box::new(Value(Int(0))) as Box<dyn Expression>].into(),
+        };
+        let res = expr.evaluate(&mut Context::default()).ok();
+        assert_eq!(res, expected, "{:?}", predict);
+    }
+}
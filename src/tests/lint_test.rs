@@ -0,0 +1,83 @@
+use pretty_assertions::assert_eq;
+
+use crate::compile;
+use crate::lint::lint;
+
+fn lint_code(code: &str) -> Vec<String> {
+    lint(&compile(code.to_string()).unwrap())
+}
+
+#[test]
+fn test_lint_clean_code() {
+    let code = r#"
+let i = 0
+for i < 3 {
+    i = i + 1
+}
+println(i)
+"#;
+    assert_eq!(lint_code(code), Vec::<String>::new());
+}
+
+#[test]
+fn test_lint_reports_issues() {
+    let code = r#"
+def f(n){
+    return n
+    println(n)
+}
+let unused = 1
+let _ignored = 2
+let i = 0
+for i < 3 {
+    let i = 5
+    println(i)
+}
+missing = 3
+println(f(nothing))
+"#;
+    assert_eq!(
+        lint_code(code),
+        vec![
+            "第 4 行: return 后面的语句不会被执行",
+            "第 10 行: 循环变量 i 在循环体里被重新声明, 循环条件用的还是外面的 i",
+            "第 13 行: 给没有声明的变量 missing 赋值",
+            "第 14 行: 使用了没有声明的变量 nothing",
+            "第 6 行: 变量 unused 声明了但是没有使用",
+        ]
+    );
+}
+
+#[test]
+fn test_lint_unreachable_without_line() {
+    let code = r#"
+def f(){
+    return 1
+
+    if true {
+    }
+}
+f()
+"#;
+    assert_eq!(
+        lint_code(code),
+        vec!["第 3 行的 return 后面的语句不会被执行"]
+    );
+}
+
+#[test]
+fn test_lint_function_cannot_see_outer_variable() {
+    let code = r#"
+let a = 1
+def f(){
+    a
+}
+println(a)
+"#;
+    assert_eq!(lint_code(code), vec!["第 4 行: 使用了没有声明的变量 a"]);
+}
+
+#[test]
+fn test_lint_assign_in_condition_is_parse_error() {
+    assert!(compile("let a = 1\nif a = 2 {\n}\n".to_string()).is_err());
+}
@@ -1,9 +1,12 @@
 mod expression_test;
+mod lint_test;
 mod parse_test;
+mod run_test;
+#[rustfmt::skip]
 mod token_test;
 
 #[cfg(test)]
-mod tests {
+mod diff_test {
     use pretty_assertions::assert_eq;
 
     #[test]
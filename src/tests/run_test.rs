@@ -0,0 +1,1089 @@
+use anyhow::Result;
+use pretty_assertions::assert_eq;
+
+use crate::context::Context;
+use crate::expression::{Value, DEFAULT_MAX_CALL_DEPTH};
+use crate::token::tokenlizer_with_line;
+use crate::{
+    compile, decode_source, evaluate, execute, execute_in, parser, run_code, run_timed,
+    LARGE_STACK_MAX_CALL_DEPTH,
+};
+
+fn eval(code: &str, call_main: bool) -> Result<Value> {
+    let ast = parser(tokenlizer_with_line(code.to_string())?)?;
+    evaluate(&mut Context::default(), &ast, call_main)
+}
+
+#[test]
+fn test_main_not_called_by_default() {
+    let code = r#"
+def main(){
+    let b = 2
+    b
+}
+"#;
+    assert_eq!(eval(code, false).unwrap(), Value::Void);
+}
+
+#[test]
+fn test_main_called_after_top_level() {
+    let code = r#"
+def main(){
+    let b = 2
+    b
+}
+let a = 1
+"#;
+    assert_eq!(eval(code, true).unwrap(), Value::Int(2));
+}
+
+#[test]
+fn test_without_main() {
+    let code = r#"
+let a = 1
+a
+"#;
+    assert_eq!(eval(code, true).unwrap(), Value::Int(1));
+}
+
+#[test]
+fn test_main_with_params() {
+    let code = r#"
+def main(args){
+    args
+}
+"#;
+    assert!(eval(code, true).is_err());
+}
+
+#[test]
+fn test_call_without_arguments() {
+    let code = r#"
+def one(){
+    1
+}
+let a = 0
+a = one()
+a
+"#;
+    assert_eq!(eval(code, false).unwrap(), Value::Int(1));
+}
+
+#[test]
+fn test_compound_assign_local() {
+    let code = r#"
+let a = 10
+a += 5
+a -= 3
+a *= 4
+a /= 6
+a %= 5
+a
+"#;
+    assert_eq!(eval(code, false).unwrap(), Value::Int(3));
+}
+
+#[test]
+fn test_compound_assign_outer_variable() {
+    let code = r#"
+let sum = 0
+let i = 0
+for i < 5 {
+    sum += i
+    i += 1
+}
+sum
+"#;
+    assert_eq!(eval(code, false).unwrap(), Value::Int(10));
+}
+
+#[test]
+fn test_compound_assign_string() {
+    let code = r#"
+let s = "a"
+s += 1
+s
+"#;
+    assert_eq!(eval(code, false).unwrap(), Value::Str("a1".to_string()));
+}
+
+#[test]
+fn test_compound_assign_const() {
+    let code = r#"
+const a = 1
+a += 1
+"#;
+    assert!(eval(code, false).is_err());
+}
+
+#[test]
+fn test_while_loop() {
+    let code = r#"
+let i = 0
+while i < 10 {
+    i = i + 1
+}
+i
+"#;
+    assert_eq!(eval(code, false).unwrap(), Value::Int(10));
+}
+
+#[test]
+fn test_block_ends_with_let() {
+    let code = r#"
+def f(){
+    let a = 1
+}
+let a = 1
+a = f()
+a
+"#;
+    assert_eq!(eval(code, false).unwrap(), Value::Void);
+}
+
+#[test]
+fn test_block_ends_with_assign() {
+    let code = r#"
+let a = 1
+if true {
+    a = 2
+}
+"#;
+    assert_eq!(eval(code, false).unwrap(), Value::Void);
+}
+
+#[test]
+fn test_empty_block() {
+    let code = r#"
+def f(){
+}
+let a = 1
+a = f()
+if a == 1 {
+} else {
+}
+"#;
+    assert_eq!(eval(code, false).unwrap(), Value::Void);
+}
+
+#[test]
+fn test_if_block_value() {
+    let code = r#"
+let a = 1
+if a == 1 {
+    let b = 2
+    b
+} else {
+    3
+}
+"#;
+    assert_eq!(eval(code, false).unwrap(), Value::Int(2));
+}
+
+#[test]
+fn test_statements_after_nested_block() {
+    let code = r#"
+let a = 1
+{
+    let b = 2
+}
+a = a + 1
+a
+"#;
+    assert_eq!(eval(code, false).unwrap(), Value::Int(2));
+}
+
+#[test]
+fn test_c_style_for() {
+    let code = r#"
+let sum = 0
+for let i = 0; i < 5; i += 1 {
+    sum += i
+}
+sum
+"#;
+    assert_eq!(eval(code, false).unwrap(), Value::Int(10));
+}
+
+#[test]
+fn test_c_style_for_missing_clauses() {
+    let code = r#"
+let i = 0
+for ; i < 5; {
+    i += 1
+}
+for ;; {
+    i += 1
+}
+"#;
+    assert!(parser(tokenlizer_with_line(code.to_string()).unwrap()).is_ok());
+
+    let code = r#"
+let i = 0
+for ; i < 5; {
+    i += 1
+}
+i
+"#;
+    assert_eq!(eval(code, false).unwrap(), Value::Int(5));
+}
+
+#[test]
+fn test_c_style_for_step_uses_loop_variable() {
+    let code = r#"
+let count = 0
+for let i = 0; i < 3; i = i + 1 {
+    let i = 100
+    count += 1
+}
+count
+"#;
+    assert_eq!(eval(code, false).unwrap(), Value::Int(3));
+}
+
+#[test]
+fn test_while_same_as_for() {
+    let body = r#" x < 10 {
+    x = x + 1
+}
+x
+"#;
+    let while_code = format!("let x = 0\nwhile{}", body);
+    let for_code = format!("let x = 0\nfor{}", body);
+    assert_eq!(
+        eval(&while_code, false).unwrap(),
+        eval(&for_code, false).unwrap()
+    );
+    assert_eq!(eval(&while_code, false).unwrap(), Value::Int(10));
+}
+
+#[test]
+fn test_call_undefined_function() {
+    let code = r#"
+let a = 0
+a = f(1)
+"#;
+    assert!(eval(code, false).is_err());
+}
+
+#[test]
+fn test_call_non_function() {
+    let code = r#"
+let a = 1
+a(1)
+"#;
+    let err = eval(code, false).unwrap_err();
+    assert!(err.to_string().contains("不是函数"));
+}
+
+#[test]
+fn test_c_style_for_with_parens() {
+    let code = r#"
+let sum = 0
+for (let i = 0; i < 5; i = i + 1) {
+    sum += i
+}
+sum
+"#;
+    assert_eq!(eval(code, false).unwrap(), Value::Int(10));
+}
+
+#[test]
+fn test_c_style_for_variable_not_visible_after_loop() {
+    let code = r#"
+for (let i = 0; i < 5; i = i + 1) {
+}
+i
+"#;
+    let err = eval(code, false).unwrap_err();
+    assert!(err.to_string().contains("未定义的变量 i"));
+}
+
+#[test]
+fn test_malformed_declare_is_error() {
+    for code in [
+        "for let i; i < 3; i += 1 {\n}\n",
+        "for let 5 = 1; true; {\n}\n",
+        "let\n",
+        "let x\n",
+        "const y 1\n",
+    ] {
+        let err = eval(code, false).unwrap_err();
+        assert!(
+            err.to_string()
+                .starts_with("声明语句应该是 `let 变量名 = 表达式`"),
+            "{}",
+            err
+        );
+    }
+}
+
+#[test]
+fn test_unicode_identifier() {
+    let code = r#"
+let 数量 = 2
+数量 = 数量 * 3
+数量
+"#;
+    assert_eq!(eval(code, false).unwrap(), Value::Int(6));
+}
+
+#[test]
+fn test_operator_priority() {
+    let code = r#"
+let a = 1 << 2 + 1
+let b = 6 & 3 == 2
+let c = 1 | 6 & 3
+let d = a + 1 == 9
+let r = 0
+if b && d {
+    r = a + c
+}
+r
+"#;
+    assert_eq!(eval(code, false).unwrap(), Value::Int(11));
+}
+
+#[test]
+fn test_string_interpolation() {
+    let code = r#"
+let name = "chen"
+let count = 2
+let s = "Hello ${name}, you have ${count + 1} messages"
+s
+"#;
+    assert_eq!(
+        eval(code, false).unwrap(),
+        Value::Str("Hello chen, you have 3 messages".to_string())
+    );
+}
+
+#[test]
+fn test_string_interpolation_only_expressions() {
+    let code = r#"
+let a = 1
+let b = 2
+let s = "${a}${b}${}"
+s
+"#;
+    assert_eq!(eval(code, false).unwrap(), Value::Str("12".to_string()));
+}
+
+#[test]
+fn test_string_interpolation_escape() {
+    let code = r#"
+let a = 1
+let s = "\${a}" + '${a}'
+s
+"#;
+    assert_eq!(
+        eval(code, false).unwrap(),
+        Value::Str("${a}${a}".to_string())
+    );
+}
+
+#[test]
+fn test_string_escape() {
+    let code = r#"
+let s = "line1\nline2\t\u{1F600}"
+s
+"#;
+    assert_eq!(
+        eval(code, false).unwrap(),
+        Value::Str("line1\nline2\t😀".to_string())
+    );
+}
+
+#[test]
+fn test_string_interpolation_nested() {
+    let code = r#"
+let a = 1
+let s = "[${"<${a + 1}>" + "}"}] ${(a + 2) * 2}"
+s
+"#;
+    assert_eq!(
+        eval(code, false).unwrap(),
+        Value::Str("[<2>}] 6".to_string())
+    );
+}
+
+#[test]
+fn test_string_interpolation_empty() {
+    let code = r#"
+let s = "a${}b${ }c"
+s
+"#;
+    assert_eq!(eval(code, false).unwrap(), Value::Str("abc".to_string()));
+}
+
+#[test]
+fn test_trace_returns_value() {
+    let code = r#"
+let x = trace(5) + 1
+x
+"#;
+    assert_eq!(eval(code, false).unwrap(), Value::Int(6));
+}
+
+#[test]
+fn test_call_in_expression() {
+    let code = r#"
+def add(a, b, c) {
+    a + b + c
+}
+let x = add(1, add(1, 1, 1), trace(2 * 3)) * 2
+x
+"#;
+    assert_eq!(eval(code, false).unwrap(), Value::Int(20));
+}
+
+#[test]
+fn test_call_as_last_expression() {
+    let code = r#"
+def one() {
+    1
+}
+one() + one()
+"#;
+    assert_eq!(eval(code, false).unwrap(), Value::Int(2));
+}
+
+#[test]
+fn test_user_function_shadows_builtin() {
+    let code = r#"
+def trace(a) {
+    a + 100
+}
+trace(1)
+"#;
+    assert_eq!(eval(code, false).unwrap(), Value::Int(101));
+}
+
+#[test]
+fn test_variable_shadows_builtin() {
+    let code = r#"
+let len = 3
+len("abc")
+"#;
+    let err = eval(code, false).unwrap_err();
+    assert_eq!(err.to_string(), "len 不是函数, 不能调用");
+}
+
+#[test]
+fn test_disable_builtin() {
+    let code = r#"
+def roll() {
+    if true {
+        random_int(1, 1)
+    }
+}
+roll()
+"#;
+    let ast = compile(code.to_string()).unwrap();
+    let mut ctx = Context::default();
+    ctx.disable_builtin("random_int");
+    let err = execute_in(&mut ctx, &ast).unwrap_err();
+    assert_eq!(err.to_string(), "内置函数 random_int 在沙箱里被禁用了");
+
+    let mut ctx = Context::default();
+    ctx.disable_builtin("random_int");
+    let len = compile("len(\"abc\")\n".to_string()).unwrap();
+    assert_eq!(execute_in(&mut ctx, &len).unwrap(), Value::Int(3));
+
+    // 新的上下文不受影响
+    assert_eq!(execute(&ast).unwrap(), Value::Int(1));
+}
+
+#[test]
+fn test_sandbox_hostile_recursion_is_error() {
+    // 沙箱在调用方的线程里执行, 无限递归也只能返回错误, 不能让宿主栈溢出
+    let code = "def f(n){\n    return 1 + f(n)\n}\nf(0)\n";
+    let ast = compile(code.to_string()).unwrap();
+    let mut ctx = Context::default();
+    ctx.disable_builtin("random_int");
+    let err = execute_in(&mut ctx, &ast).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        format!(
+            "函数调用层数超过了 {} 层, 可能是无限递归, 调用 f 时栈溢出",
+            DEFAULT_MAX_CALL_DEPTH
+        )
+    );
+}
+
+#[test]
+fn test_builtin_arity() {
+    let err = eval("trace(1, 2)\n", false).unwrap_err();
+    assert_eq!(err.to_string(), "函数 trace 需要 1 个参数, 实际传入了 2 个");
+}
+
+#[test]
+fn test_math_builtins() {
+    let code = r#"
+let a = max(2, 9) * 1000000 + min(2, 9) * 100000
+a + abs(0 - 3) * 1000 + pow(2, 5)
+"#;
+    assert_eq!(eval(code, false).unwrap(), Value::Int(9203032));
+}
+
+#[test]
+fn test_math_builtins_error() {
+    let err = eval("pow(2, 63)\n", false).unwrap_err();
+    assert_eq!(err.to_string(), "函数 pow 计算结果溢出");
+    let err = eval("pow(2, 0 - 1)\n", false).unwrap_err();
+    assert_eq!(err.to_string(), "函数 pow 的指数不能是负数, -1");
+    let err = eval("abs(true)\n", false).unwrap_err();
+    assert_eq!(err.to_string(), "函数 abs 的参数必须是整数, Bool(true)");
+}
+
+#[test]
+fn test_shadowing_in_block() {
+    let code = r#"
+let x = 1
+let inner = 0
+{
+    let x = 2
+    inner = x
+}
+inner * 10 + x
+"#;
+    assert_eq!(eval(code, false).unwrap(), Value::Int(21));
+}
+
+#[test]
+fn test_assign_in_block_updates_outer() {
+    let code = r#"
+let x = 1
+{
+    x = 2
+    {
+        let x = 3
+        x = 4
+    }
+}
+x
+"#;
+    assert_eq!(eval(code, false).unwrap(), Value::Int(2));
+}
+
+#[test]
+fn test_shadowing_in_loop_and_if() {
+    let code = r#"
+let x = 100
+let sum = 0
+for let i = 0; i < 3; i += 1 {
+    let x = i
+    if x == 1 {
+        let x = 10
+        sum += x
+    } else {
+        sum += x
+    }
+}
+sum + x
+"#;
+    assert_eq!(eval(code, false).unwrap(), Value::Int(112));
+}
+
+#[test]
+fn test_redeclare_in_same_block() {
+    let code = r#"
+{
+    let x = 1
+    let x = 2
+}
+"#;
+    let err = eval(code, false).unwrap_err();
+    assert_eq!(err.to_string(), "重复定义变量 x");
+}
+
+#[test]
+fn test_redeclare_same_scope() {
+    let code = r#"
+let x = 1
+const x = 2
+"#;
+    let err = eval(code, false).unwrap_err();
+    assert_eq!(err.to_string(), "重复定义变量 x");
+}
+
+#[test]
+fn test_shadow_param() {
+    let code = r#"
+def f(a) {
+    let a = a * 2
+    a
+}
+f(21)
+"#;
+    assert_eq!(eval(code, false).unwrap(), Value::Int(42));
+}
+
+#[test]
+fn test_decode_source_with_bom() {
+    let code = decode_source(b"\xEF\xBB\xBFlet a = 1\na + 1\n".to_vec()).unwrap();
+    assert_eq!(code, "let a = 1\na + 1\n");
+    assert_eq!(eval(&code, false).unwrap(), Value::Int(2));
+}
+
+#[test]
+fn test_decode_source_invalid_utf8() {
+    let err = decode_source(b"let a = \"\xFF\"\n".to_vec()).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "源文件不是有效的 UTF-8 编码, 位置: 第 10 个字节"
+    );
+}
+
+#[test]
+fn test_string_builtins() {
+    let code = r#"
+let s = to_upper(trim("  Hello, 世界  "))
+s = replace(s, "L", "l")
+if contains(s, "世界") {
+    s + to_lower("!ABC") + substring("你好世界", 1, 3)
+}
+"#;
+    assert_eq!(
+        eval(code, false).unwrap(),
+        Value::Str("HEllO, 世界!abc好世".to_string())
+    );
+}
+
+#[test]
+fn test_substring_out_of_range() {
+    let err = eval("substring(\"你好\", 1, 3)\n", false).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "substring 下标越界, 字符串长度 2, 下标 [1, 3)"
+    );
+    let err = eval("trim(1)\n", false).unwrap_err();
+    assert_eq!(err.to_string(), "函数 trim 的参数必须是字符串, Int(1)");
+}
+
+#[test]
+fn test_ternary() {
+    let code = r#"
+let x = (1 < 2) ? 10 : 20
+let y = x > 10 || false ? 1 : x == 10 && true ? 2 : 3
+let z = (y == 2 ? "a" : "b") + "${x < 0 ? "-" : "+"}"
+z + x + y
+"#;
+    assert_eq!(eval(code, false).unwrap(), Value::Str("a+102".to_string()));
+}
+
+#[test]
+fn test_ternary_missing_colon() {
+    let err = eval("let x = true ? 1\n", false).unwrap_err();
+    assert!(err.to_string().starts_with("三元表达式缺少 `:`"));
+}
+
+#[test]
+fn test_len() {
+    let code = r#"
+len("") * 100 + len("abc") * 10 + len("你好世界")
+"#;
+    assert_eq!(eval(code, false).unwrap(), Value::Int(34));
+    let err = eval("len(1)\n", false).unwrap_err();
+    assert_eq!(err.to_string(), "函数 len 的参数必须是字符串, Int(1)");
+}
+
+#[test]
+fn test_execute_compiled_program_twice() {
+    let ast = compile("let a = 1\na = a + 1\na".to_string()).unwrap();
+    assert_eq!(execute(&ast).unwrap(), Value::Int(2));
+    assert_eq!(execute(&ast).unwrap(), Value::Int(2));
+}
+
+#[test]
+fn test_power_precedence() {
+    let code = r#"
+let a = 2 ** 3 ** 2
+let b = 2 * 3 ** 2
+let c = (2 ** 3) ** 2
+a * 10000 + b * 100 + c
+"#;
+    assert_eq!(eval(code, false).unwrap(), Value::Int(5121864));
+}
+
+#[test]
+fn test_assign_in_expression() {
+    let code = r#"
+let x = 1
+if x = 5 {
+    x
+}
+"#;
+    let err = eval(code, false).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "第 3 行: 表达式里不能用 `=` 赋值, 是不是想写 `==`?"
+    );
+
+    let code = r#"
+def f(x) {
+    let y = 1
+
+    return x = y
+}
+"#;
+    let err = eval(code, false).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "第 5 行: 表达式里不能用 `=` 赋值, 是不是想写 `==`?"
+    );
+
+    let err = eval("let x = 1\nlet y = x += 1\n", false).unwrap_err();
+    assert!(err.to_string().starts_with("表达式里不能用 AddAssign 赋值"));
+}
+
+#[test]
+fn test_run_timed() {
+    let code = r#"
+def main() {
+    let sum = 0
+    for let i = 0; i < 100; i += 1 {
+        sum += i
+    }
+}
+"#;
+    let timings = run_timed(code.to_string(), true).unwrap();
+    assert!(timings.execute > std::time::Duration::ZERO);
+    assert!(run_timed("main(\n".to_string(), false).is_err());
+}
+
+#[test]
+fn test_int_overflow_is_error() {
+    let err = eval("let a = 9223372036854775807 + 1\n", false).unwrap_err();
+    assert_eq!(err.to_string(), "整数运算溢出, 9223372036854775807 + 1");
+    let err = eval("let a = 4294967296\na *= a\n", false).unwrap_err();
+    assert_eq!(err.to_string(), "整数运算溢出, 4294967296 * 4294967296");
+}
+
+#[test]
+fn test_error_under_not_is_not_panic() {
+    let err = eval("println(!(9223372036854775807 + 1 == 0))\n", false).unwrap_err();
+    assert_eq!(err.to_string(), "整数运算溢出, 9223372036854775807 + 1");
+    let err = eval("def f(n){\n    return !f(n)\n}\nf(0)\n", false).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        format!(
+            "函数调用层数超过了 {} 层, 可能是无限递归, 调用 f 时栈溢出",
+            DEFAULT_MAX_CALL_DEPTH
+        )
+    );
+}
+
+#[test]
+fn test_int_beyond_i32() {
+    let code = r#"
+let ms = 1_700_000_000_000
+ms / 1000 + 2147483647 * 2
+"#;
+    assert_eq!(eval(code, false).unwrap(), Value::Int(5994967294));
+}
+
+#[test]
+fn test_return() {
+    let code = r#"
+def explicit(a) {
+    return a + 1
+}
+def implicit(a) {
+    a + 2
+}
+def empty() {
+}
+def bare() {
+    return
+}
+explicit(1) * 10 + implicit(1) + "${empty()}${bare()}"
+"#;
+    assert_eq!(eval(code, false).unwrap(), Value::Str("23".to_string()));
+}
+
+#[test]
+fn test_early_return() {
+    let code = r#"
+def first_multiple(n, limit) {
+    for let i = 1; i < limit; i += 1 {
+        if i % n == 0 {
+            return i
+        }
+    }
+    return 0 - 1
+}
+first_multiple(7, 100) * 1000 + first_multiple(7, 5)
+"#;
+    assert_eq!(eval(code, false).unwrap(), Value::Int(6999));
+}
+
+#[test]
+fn test_return_outside_function() {
+    let err = eval("return 1\n", false).unwrap_err();
+    assert_eq!(err.to_string(), "return 只能在函数里使用");
+}
+
+#[test]
+fn test_compare() {
+    let code = r#"
+def cmp(a, b) {
+    return compare(a, b)
+}
+let s = "" + cmp(1, 2) + cmp(2, 2) + cmp(3, 2)
+s = s + cmp("abc", "abd") + cmp("b", "a") + cmp(false, true)
+s + cmp(1, "1") + cmp(true, 0) + cmp("", 100)
+"#;
+    assert_eq!(
+        eval(code, false).unwrap(),
+        Value::Str("-101-11-1-1-11".to_string())
+    );
+}
+
+#[test]
+fn test_bit_not() {
+    let code = r#"
+let a = ~0 == -1
+let b = ~~5 + ~1 * 2
+let c = 1 == 2 || !!true
+"${a} ${b} ${c} ${6 & ~2}"
+"#;
+    assert_eq!(
+        eval(code, false).unwrap(),
+        Value::Str("true 1 true 4".to_string())
+    );
+}
+
+#[test]
+fn test_random_seed() {
+    let code = r#"
+let s = ""
+for let i = 0; i < 10; i += 1 {
+    s = s + random_int(1, 6)
+}
+s
+"#;
+    let run = |seed: &str| eval(&format!("random_seed({})\n{}", seed, code), false).unwrap();
+    assert_eq!(run("42"), run("42"));
+    assert_ne!(run("42"), run("7"));
+    assert_eq!(run("42"), Value::Str("5262344624".to_string()));
+}
+
+#[test]
+fn test_random_seed_is_per_context() {
+    let code = r#"
+let s = ""
+for let i = 0; i < 20; i += 1 {
+    s = s + random_int(1, 6)
+}
+s
+"#;
+    let seeded = compile(format!("random_seed(42)\n{}", code)).unwrap();
+    let unseeded = compile(code.to_string()).unwrap();
+    let expected = execute(&seeded).unwrap();
+    assert_eq!(execute(&seeded).unwrap(), expected);
+
+    // 种子只影响设置它的那个上下文, 之后新的上下文还是用自己的种子
+    assert_ne!(
+        execute_in(&mut Context::default(), &unseeded).unwrap(),
+        expected
+    );
+    assert_ne!(
+        execute_in(&mut Context::default(), &unseeded).unwrap(),
+        execute_in(&mut Context::default(), &unseeded).unwrap()
+    );
+}
+
+#[test]
+fn test_type() {
+    let code = r#"
+def nothing() {
+}
+"${type(1)} ${type("x")} ${type(1 < 2)} ${type(nothing())}"
+"#;
+    assert_eq!(
+        eval(code, false).unwrap(),
+        Value::Str("int string bool void".to_string())
+    );
+}
+
+#[test]
+fn test_coalesce() {
+    let code = r#"
+def nothing() {
+}
+def boom() {
+    return 1 / 0
+}
+let a = nothing() ?? 1 + 1
+let b = 3 ?? boom()
+let c = nothing() ?? nothing() ?? "c"
+let d = false ?? true
+"${a} ${b} ${c} ${d}"
+"#;
+    assert_eq!(
+        eval(code, false).unwrap(),
+        Value::Str("2 3 c false".to_string())
+    );
+}
+
+#[test]
+fn test_unterminated_block() {
+    let code = r#"
+let a = 1
+
+if a > 0 {
+    a = 2
+    for let i = 0; i < 3; i += 1 {
+        a += i
+    }
+"#;
+    let err = eval(code, false).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "第 4 行开始的语句块没有结束, 到第 8 行文件结束都没有找到对应的 `}`"
+    );
+
+    let code = "if true {\n    1\n} else {\n    2\n";
+    let err = eval(code, false).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "第 3 行开始的语句块没有结束, 到第 4 行文件结束都没有找到对应的 `}`"
+    );
+}
+
+#[test]
+fn test_extra_right_brace() {
+    let err = eval("let a = 1\n}\nlet b = 2\n", false).unwrap_err();
+    assert_eq!(err.to_string(), "第 2 行有多余的 `}`");
+}
+
+#[test]
+fn test_unbounded_recursion_is_error() {
+    let code = r#"
+def f(n){
+    return 1 + f(n + 1)
+}
+f(0)
+"#;
+    let err = run_code(code.to_string(), false).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "函数调用层数超过了 10000 层, 可能是无限递归, 调用 f 时栈溢出"
+    );
+}
+
+#[test]
+fn test_deep_recursion() {
+    let code = r#"
+def sum(n){
+    if n == 0 {
+        return 0
+    }
+    return n + sum(n - 1)
+}
+sum(1000)
+"#;
+    assert_eq!(
+        run_code(code.to_string(), false).unwrap(),
+        Value::Int(500500)
+    );
+}
+
+#[test]
+fn test_recursion_inside_block() {
+    let code = r#"
+def sum(n){
+    if n == 0 {
+        return 0
+    }
+    return n + sum(n - 1)
+}
+sum(100)
+"#;
+    assert_eq!(eval(code, false).unwrap(), Value::Int(5050));
+}
+
+#[test]
+fn test_set_max_call_depth() {
+    let code = r#"
+def f(n){
+    if n == 0 {
+        return 0
+    }
+    return 1 + f(n - 1)
+}
+f(10)
+"#;
+    let ast = compile(code.to_string()).unwrap();
+    let mut ctx = Context::default();
+    ctx.set_max_call_depth(10);
+    let err = execute_in(&mut ctx, &ast).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "函数调用层数超过了 10 层, 可能是无限递归, 调用 f 时栈溢出"
+    );
+
+    let mut ctx = Context::default();
+    ctx.set_max_call_depth(11);
+    assert_eq!(execute_in(&mut ctx, &ast).unwrap(), Value::Int(10));
+
+    // 设置只对那个上下文有效
+    let code = "def f(n){\n    return 1 + f(n - 1)\n}\nf(-1)\n";
+    let err = run_code(code.to_string(), false).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        format!(
+            "函数调用层数超过了 {} 层, 可能是无限递归, 调用 f 时栈溢出",
+            LARGE_STACK_MAX_CALL_DEPTH
+        )
+    );
+}
+
+#[test]
+fn test_execute_unbounded_recursion_is_error() {
+    // 在测试线程默认大小的栈里执行, 不能栈溢出
+    let code = "def f(n){\n    return 1 + f(n + 1)\n}\nf(0)\n";
+    let err = execute(&compile(code.to_string()).unwrap()).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        format!(
+            "函数调用层数超过了 {} 层, 可能是无限递归, 调用 f 时栈溢出",
+            DEFAULT_MAX_CALL_DEPTH
+        )
+    );
+}
+
+#[test]
+fn test_tail_call_does_not_grow_stack() {
+    let code = r#"
+def count_down(n){
+    if n == 0 {
+        return 0
+    }
+    return count_down(n - 1)
+}
+count_down(100000)
+"#;
+    assert_eq!(eval(code, false).unwrap(), Value::Int(0));
+}
+
+#[test]
+fn test_mutual_tail_call() {
+    let code = r#"
+def is_even(n){
+    if n == 0 {
+        return true
+    }
+    return is_odd(n - 1)
+}
+def is_odd(n){
+    if n == 0 {
+        return false
+    }
+    return is_even(n - 1)
+}
+is_even(10001)
+"#;
+    assert_eq!(eval(code, false).unwrap(), Value::Bool(false));
+}
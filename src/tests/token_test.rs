@@ -1,10 +1,12 @@
-#![rustfmt::skip]
 use crate::token;
 use crate::token::Keyword::{ELSE, FOR, IF, LET};
-use crate::token::Operator::{Assign, Equals, Mod, ADD, LT};
+use crate::token::Operator::{
+    Assign, BitNot, Coalesce, Equals, Mod, Multiply, Power, ADD, GT, GTE, LT,
+};
 use crate::token::StdFunction::Print;
 use crate::token::Token::{
     Identifier, Int, Keyword, LBig, LParen, NewLine, Operator, RBig, RParen, StdFunction, String,
+    COLON, FatArrow, QUESTION,
 };
 use pretty_assertions::assert_eq;
 use crate::Keyword::DEF;
@@ -110,3 +112,232 @@ println(sum)
         ],
     );
 }
+
+#[test]
+fn test_parse_compound_assign() {
+    use crate::Operator::{AddAssign, DivideAssign, ModAssign, MultiplyAssign, SubtractAssign};
+    assert_eq!(
+        token::tokenlizer("a += 1 a -= 1 a *= 1 a /= 1 a %= 1".to_string()).unwrap(),
+        vec![
+            Identifier("a".to_string()), Operator(AddAssign), Int(1),
+            Identifier("a".to_string()), Operator(SubtractAssign), Int(1),
+            Identifier("a".to_string()), Operator(MultiplyAssign), Int(1),
+            Identifier("a".to_string()), Operator(DivideAssign), Int(1),
+            Identifier("a".to_string()), Operator(ModAssign), Int(1),
+        ]
+    );
+}
+
+#[test]
+fn test_parse_while() {
+    use crate::token::Keyword::WHILE;
+    assert_eq!(
+        token::tokenlizer("while i < 10 {".to_string()).unwrap(),
+        vec![Keyword(WHILE), Identifier("i".to_string()), Operator(LT), Int(10), LBig]
+    )
+}
+
+#[test]
+fn test_parse_semicolon() {
+    use crate::token::Token::SEMICOLON;
+    assert_eq!(
+        token::tokenlizer("for ;; {".to_string()).unwrap(),
+        vec![Keyword(FOR), SEMICOLON, SEMICOLON, LBig]
+    )
+}
+
+#[test]
+fn test_parse_unicode_identifier() {
+    assert_eq!(
+        token::tokenlizer("let 变量_1 = _a".to_string()).unwrap(),
+        vec![Keyword(LET), Identifier("变量_1".to_string()), Operator(Assign), Identifier("_a".to_string())]
+    )
+}
+
+#[test]
+fn test_parse_bit_operators() {
+    use crate::Operator::{And, BitAnd, BitOr, BitXor, ShiftLeft, ShiftRight, GTE, LTE};
+    assert_eq!(
+        token::tokenlizer("a & b | c ^ d << e >> f && g || h <= i >= j".to_string()).unwrap(),
+        vec![
+            Identifier("a".to_string()), Operator(BitAnd),
+            Identifier("b".to_string()), Operator(BitOr),
+            Identifier("c".to_string()), Operator(BitXor),
+            Identifier("d".to_string()), Operator(ShiftLeft),
+            Identifier("e".to_string()), Operator(ShiftRight),
+            Identifier("f".to_string()), Operator(And),
+            Identifier("g".to_string()), Operator(Or),
+            Identifier("h".to_string()), Operator(LTE),
+            Identifier("i".to_string()), Operator(GTE),
+            Identifier("j".to_string()),
+        ]
+    );
+}
+
+#[test]
+fn test_parse_template() {
+    use crate::token::TemplatePart;
+    assert_eq!(
+        token::tokenlizer(r#""a${b + 1}c" '${b}' "\${b}""#.to_string()).unwrap(),
+        vec![
+            token::Token::Template(vec![
+                TemplatePart::Str("a".to_string()),
+                TemplatePart::Expr(vec![Identifier("b".to_string()), Operator(ADD), Int(1)]),
+                TemplatePart::Str("c".to_string()),
+            ]),
+            String("${b}".to_string()),
+            String("${b}".to_string()),
+        ]
+    );
+}
+
+#[test]
+fn test_parse_unterminated_template() {
+    assert!(token::tokenlizer(r#""a${b""#.to_string()).is_err());
+}
+
+#[test]
+fn test_parse_escape() {
+    assert_eq!(
+        token::tokenlizer(r#""a\n\t\r\"\'\\\u{4e2d}b" 'c\'d'"#.to_string()).unwrap(),
+        vec![String("a\n\t\r\"'\\中b".to_string()), String("c'd".to_string())]
+    );
+}
+
+#[test]
+fn test_parse_invalid_escape() {
+    let err = token::tokenlizer("\n\"a\\qb\"".to_string()).unwrap_err();
+    assert_eq!(err.to_string(), r#"invalid escape sequence "\\q" at line 2"#);
+    assert!(token::tokenlizer(r#""\u{110000}""#.to_string()).is_err());
+    assert!(token::tokenlizer(r#""\u{zz}""#.to_string()).is_err());
+}
+
+#[test]
+fn test_parse_unterminated_string() {
+    let err = token::tokenlizer("let a = 1\nlet b = \"abc".to_string()).unwrap_err();
+    assert_eq!(err.to_string(), "unterminated string at line 2");
+}
+
+#[test]
+fn test_tokenlizer_with_line() {
+    assert_eq!(
+        token::tokenlizer_with_line("a\n\n  b\n# c\n".to_string()).unwrap(),
+        vec![
+            (Identifier("a".to_string()), 1),
+            (NewLine, 1),
+            (NewLine, 2),
+            (Identifier("b".to_string()), 3),
+            (NewLine, 3),
+        ]
+    );
+}
+
+#[test]
+fn test_parse_number_separator() {
+    assert_eq!(
+        token::tokenlizer("1_000_000 -2_5 _5".to_string()).unwrap(),
+        vec![Int(1000000), Int(-25), Identifier("_5".to_string())]
+    );
+}
+
+#[test]
+fn test_parse_invalid_number_separator() {
+    let err = token::tokenlizer("\n1__000".to_string()).unwrap_err();
+    assert_eq!(err.to_string(), r#"invalid number "1__000" at line 2"#);
+    let err = token::tokenlizer("1_ + 2".to_string()).unwrap_err();
+    assert_eq!(err.to_string(), r#"invalid number "1_" at line 1"#);
+}
+
+#[test]
+fn test_parse_question() {
+    assert_eq!(
+        token::tokenlizer("a ? 1 : 2".to_string()).unwrap(),
+        vec![
+            Identifier("a".to_string()),
+            QUESTION,
+            Int(1),
+            COLON,
+            Int(2)
+        ]
+    );
+}
+
+#[test]
+fn test_parse_power() {
+    assert_eq!(
+        token::tokenlizer("2 ** 3 * 4".to_string()).unwrap(),
+        vec![Int(2), Operator(Power), Int(3), Operator(Multiply), Int(4)]
+    );
+}
+
+#[test]
+fn test_parse_fat_arrow() {
+    assert_eq!(
+        token::tokenlizer("=> >= == = > =>=".to_string()).unwrap(),
+        vec![FatArrow, Operator(GTE), Operator(Equals), Operator(Assign), Operator(GT), FatArrow, Operator(Assign)]
+    );
+}
+
+#[test]
+fn test_parse_bit_not() {
+    assert_eq!(
+        token::tokenlizer("~a".to_string()).unwrap(),
+        vec![Operator(BitNot), Identifier("a".to_string())]
+    );
+}
+
+#[test]
+fn test_parse_coalesce() {
+    assert_eq!(
+        token::tokenlizer("a ?? b ? c : d".to_string()).unwrap(),
+        vec![
+            Identifier("a".to_string()), Operator(Coalesce), Identifier("b".to_string()),
+            QUESTION, Identifier("c".to_string()), COLON, Identifier("d".to_string()),
+        ]
+    );
+}
+
+#[test]
+fn test_location_debug_with_tab() {
+    let raw: Vec<char> = "let a = 1\n\tlet b = @\nx\ty\n".chars().collect();
+    let loc = token::Location::new(2, 10, 19);
+    assert_eq!(loc.debug(&raw, "unknown token"), "unknown token\n\n    let b = @\n            ^ Near here");
+    assert_eq!(loc.debug_with_tab_width(&raw, "e", 8), "e\n\n        let b = @\n                ^ Near here");
+    let loc = token::Location::new(3, 3, 23);
+    assert_eq!(loc.debug(&raw, "e"), "e\n\nx   y\n    ^ Near here");
+    let loc = token::Location::new(1, 1, 0);
+    assert_eq!(loc.debug(&raw, "e"), "e\n\nlet a = 1\n^ Near here");
+}
+
+#[test]
+fn test_parse_comment_at_eof() {
+    use crate::token::TemplatePart;
+    assert_eq!(
+        token::tokenlizer("let a = 1 # no newline".to_string()).unwrap(),
+        vec![Keyword(LET), Identifier("a".to_string()), Operator(Assign), Int(1)]
+    );
+    assert_eq!(
+        token::tokenlizer(r#""${#}""#.to_string()).unwrap(),
+        vec![token::Token::Template(vec![TemplatePart::Str("".to_string()), TemplatePart::Expr(vec![])])]
+    );
+}
+
+#[test]
+fn test_token_error_snippet() {
+    let err = token::tokenlizer("let a = 1\n\tlet b = @\n".to_string()).unwrap_err();
+    assert_eq!(err.to_string(), "UnknownToken '@'");
+    assert_eq!(err.snippet(), Some("UnknownToken '@'\n\n    let b = @\n            ^ Near here"));
+    // 插值里的错误指向整个字符串
+    let err = token::tokenlizer("let a = \"x ${ b @ }\"".to_string()).unwrap_err();
+    assert_eq!(err.snippet(), Some("UnknownToken '@'\n\nlet a = \"x ${ b @ }\"\n        ^ Near here"));
+}
+
+#[test]
+fn test_crlf_line_numbers() {
+    assert_eq!(
+        token::tokenlizer_with_line("a\r\nb\r\n".to_string()).unwrap(),
+        vec![(Identifier("a".to_string()), 1), (NewLine, 1), (Identifier("b".to_string()), 2), (NewLine, 2)]
+    );
+    let err = token::tokenlizer("let a = 1\r\nlet b = @\r\n".to_string()).unwrap_err();
+    assert_eq!(err.snippet(), Some("UnknownToken '@'\n\nlet b = @\n        ^ Near here"));
+}
@@ -10,6 +10,46 @@ pub enum TokenError {
     Disconnect(#[from] ParseIntError),
     #[error("unknown error")]
     Unknown,
+    #[error("unterminated string at line {line}")]
+    UnterminatedString { line: usize },
+    #[error("unterminated string interpolation at line {line}")]
+    UnterminatedInterpolation { line: usize },
+    #[error("invalid escape sequence {escape:?} at line {line}")]
+    InvalidEscape { escape: String, line: usize },
+    #[error("invalid number {number:?} at line {line}")]
+    InvalidNumber { number: String, line: usize },
+    /// 带着出错位置的错误, `snippet` 是用 [`Location::debug`] 渲染好的出错的那一行
+    #[error("{error}")]
+    At {
+        error: Box<TokenError>,
+        location: Location,
+        snippet: String,
+    },
+}
+
+impl TokenError {
+    /// 加上出错的位置, 已经有位置的话用新的位置替换
+    ///
+    /// 字符串插值里的代码是单独分析的, 里面的位置是相对插值代码的, 所以外层的位置更准
+    fn at(self, location: Location, raw: &[char]) -> TokenError {
+        let error = match self {
+            TokenError::At { error, .. } => error,
+            error => Box::new(error),
+        };
+        TokenError::At {
+            snippet: location.debug(raw, error.to_string()),
+            error,
+            location,
+        }
+    }
+
+    /// 出错的那一行和指向出错位置的 `^`, 没有位置信息时返回 `None`
+    pub fn snippet(&self) -> Option<&str> {
+        match self {
+            TokenError::At { snippet, .. } => Some(snippet),
+            _ => None,
+        }
+    }
 }
 
 /// 关键字
@@ -23,6 +63,8 @@ pub enum Keyword {
     ELSE,
     /// for
     FOR,
+    /// while
+    WHILE,
     /// def
     DEF,
     /// return
@@ -42,8 +84,20 @@ pub enum Operator {
     Divide,
     /// %
     Mod,
+    /// **
+    Power,
     /// =
     Assign,
+    /// +=
+    AddAssign,
+    /// -=
+    SubtractAssign,
+    /// *=
+    MultiplyAssign,
+    /// /=
+    DivideAssign,
+    /// %=
+    ModAssign,
     /// &&
     And,
     /// ==
@@ -62,6 +116,39 @@ pub enum Operator {
     GTE,
     /// <=
     LTE,
+    /// &
+    BitAnd,
+    /// |
+    BitOr,
+    /// ^
+    BitXor,
+    /// <<
+    ShiftLeft,
+    /// >>
+    ShiftRight,
+    /// ~
+    BitNot,
+    /// ??
+    Coalesce,
+}
+
+impl Operator {
+    /// 是否是赋值运算符 (包括 `+=` 这样的复合赋值)
+    pub fn is_assign(&self) -> bool {
+        *self == Operator::Assign || self.compound_operator().is_some()
+    }
+
+    /// 复合赋值运算符对应的二元运算符, 例如 `+=` 对应 `+`
+    pub fn compound_operator(&self) -> Option<Operator> {
+        match self {
+            Operator::AddAssign => Some(Operator::ADD),
+            Operator::SubtractAssign => Some(Operator::Subtract),
+            Operator::MultiplyAssign => Some(Operator::Multiply),
+            Operator::DivideAssign => Some(Operator::Divide),
+            Operator::ModAssign => Some(Operator::Mod),
+            _ => None,
+        }
+    }
 }
 
 /// 标准库函数
@@ -71,6 +158,15 @@ pub enum StdFunction {
     Print(bool),
 }
 
+/// 字符串模板里的一段
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum TemplatePart {
+    /// 普通的字符串
+    Str(String),
+    /// `${...}` 里面的表达式
+    Expr(Vec<Token>),
+}
+
 /// token 类型
 #[derive(Debug, Eq, PartialEq, Clone)]
 pub enum Token {
@@ -79,11 +175,13 @@ pub enum Token {
     /// 操作符
     Operator(Operator),
     /// int
-    Int(i32),
+    Int(i64),
     /// bool
     Bool(bool),
     /// string
     String(String),
+    /// 带有 `${...}` 的双引号字符串
+    Template(Vec<TemplatePart>),
     /// 标识符
     Identifier(String),
     /// 标准库函数
@@ -100,6 +198,12 @@ pub enum Token {
     COLON,
     /// 逗号
     COMMA,
+    /// 分号
+    SEMICOLON,
+    /// 问号
+    QUESTION,
+    /// =>
+    FatArrow,
     /// (
     LParen,
     /// )
@@ -118,12 +222,13 @@ fn parse_token(chars: &Vec<char>, loc: &Location) -> Result<(Token, Location), T
     let res = match cur {
         '#' => {
             let mut l = loc.incr();
-            while chars[l.index] != '\n' {
+            while l.index < chars.len() && chars[l.index] != '\n' {
                 l = l.incr();
             }
             (Token::Comment, l.new_line())
         }
-        '\n' | '\r' => (Token::NewLine, loc.new_line()),
+        // `\r\n` 里的 `\r` 当作空白, 只有 `\n` 算换行
+        '\n' => (Token::NewLine, loc.new_line()),
         _ if cur.is_whitespace() => (Token::Space, loc.incr()),
         '{' => (Token::LBig, loc.incr()),
         '}' => (Token::RBig, loc.incr()),
@@ -133,37 +238,41 @@ fn parse_token(chars: &Vec<char>, loc: &Location) -> Result<(Token, Location), T
         ')' => (Token::RParen, loc.incr()),
         ':' => (Token::COLON, loc.incr()),
         ',' => (Token::COMMA, loc.incr()),
+        ';' => (Token::SEMICOLON, loc.incr()),
+        '?' if next == '?' => (Token::Operator(Operator::Coalesce), loc.incr2()),
+        '?' => (Token::QUESTION, loc.incr()),
+        '+' if next == '=' => (Token::Operator(Operator::AddAssign), loc.incr2()),
+        '-' if next == '=' => (Token::Operator(Operator::SubtractAssign), loc.incr2()),
+        '*' if next == '*' => (Token::Operator(Operator::Power), loc.incr2()),
+        '*' if next == '=' => (Token::Operator(Operator::MultiplyAssign), loc.incr2()),
+        '/' if next == '=' => (Token::Operator(Operator::DivideAssign), loc.incr2()),
+        '%' if next == '=' => (Token::Operator(Operator::ModAssign), loc.incr2()),
         '+' => (Token::Operator(Operator::ADD), loc.incr()),
         '*' => (Token::Operator(Operator::Multiply), loc.incr()),
         '/' => (Token::Operator(Operator::Divide), loc.incr()),
         '%' => (Token::Operator(Operator::Mod), loc.incr()),
         '=' if next == '=' => (Token::Operator(Operator::Equals), loc.incr2()),
+        '=' if next == '>' => (Token::FatArrow, loc.incr2()),
         '=' if next != '=' => (Token::Operator(Operator::Assign), loc.incr()),
         '&' if next == '&' => (Token::Operator(Operator::And), loc.incr2()),
+        '&' => (Token::Operator(Operator::BitAnd), loc.incr()),
         '|' if next == '|' => (Token::Operator(Operator::Or), loc.incr2()),
+        '|' => (Token::Operator(Operator::BitOr), loc.incr()),
+        '^' => (Token::Operator(Operator::BitXor), loc.incr()),
+        '~' => (Token::Operator(Operator::BitNot), loc.incr()),
         '!' if next == '=' => (Token::Operator(Operator::NotEquals), loc.incr2()),
         '!' if next != '=' => (Token::Operator(Operator::NOT), loc.incr()),
+        '<' if next == '<' => (Token::Operator(Operator::ShiftLeft), loc.incr2()),
         '<' if next == '=' => (Token::Operator(Operator::LTE), loc.incr2()),
         '<' if next != '=' => (Token::Operator(Operator::LT), loc.incr()),
+        '>' if next == '>' => (Token::Operator(Operator::ShiftRight), loc.incr2()),
         '>' if next == '=' => (Token::Operator(Operator::GTE), loc.incr2()),
         '>' if next != '=' => (Token::Operator(Operator::GT), loc.incr()),
         '-' if !next.is_numeric() => (Token::Operator(Operator::Subtract), loc.incr()),
-        '"' | '\'' => {
-            let mut l = loc.incr();
-            while cur != chars[l.index] {
-                l = match chars[l.index] {
-                    '\n' => l.new_line(),
-                    _ => l.incr(),
-                };
-            }
-            let s: String = chars.as_slice()[(loc.index + 1)..(l.index)]
-                .iter()
-                .collect();
-            (Token::String(s), l.incr())
-        }
+        '"' | '\'' => parse_string(chars, loc)?,
         _ if cur == '-' || cur.is_numeric() => {
             let mut l = loc.incr();
-            while chars[l.index].is_numeric() {
+            while l.index < chars.len() && (chars[l.index].is_numeric() || chars[l.index] == '_') {
                 l = l.incr();
             }
 
@@ -173,13 +282,21 @@ fn parse_token(chars: &Vec<char>, loc: &Location) -> Result<(Token, Location), T
                 .take(l.index - loc.index)
                 .collect();
 
-            (Token::Int(s.parse()?), l)
+            // 数字里可以用 `_` 分隔, 但是 `_` 只能夹在两个数字中间
+            if s.ends_with('_') || s.contains("__") {
+                return Err(TokenError::InvalidNumber {
+                    number: s,
+                    line: loc.line,
+                });
+            }
+
+            (Token::Int(s.replace('_', "").parse()?), l)
         }
 
-        _ if cur.is_ascii_alphabetic() => {
+        _ if cur.is_alphabetic() || cur == '_' => {
             let mut l = loc.incr();
             while l.index < chars.len()
-                && matches!(chars[l.index], 'A'..='Z' | 'a'..='z' | '0'..='9')
+                && (chars[l.index].is_alphanumeric() || chars[l.index] == '_')
             {
                 l = l.incr();
             }
@@ -195,6 +312,7 @@ fn parse_token(chars: &Vec<char>, loc: &Location) -> Result<(Token, Location), T
                 "def" => Token::Keyword(Keyword::DEF),
                 "else" => Token::Keyword(Keyword::ELSE),
                 "for" => Token::Keyword(Keyword::FOR),
+                "while" => Token::Keyword(Keyword::WHILE),
                 "true" => Token::Bool(true),
                 "false" => Token::Bool(false),
                 _ => Token::Identifier(s),
@@ -205,20 +323,136 @@ fn parse_token(chars: &Vec<char>, loc: &Location) -> Result<(Token, Location), T
             return Err(TokenError::UnknownToken { token: cur });
         }
     };
-    return Ok(res);
+    Ok(res)
+}
+
+/// 分析字符串
+///
+/// 双引号字符串里可以用 `${...}` 插入表达式, 用 `\${` 表示 `${` 本身;
+/// 单引号字符串不做插值。两种字符串都支持转义字符, 见 [`parse_escape`]
+fn parse_string(chars: &[char], loc: &Location) -> Result<(Token, Location), TokenError> {
+    let quote = chars[loc.index];
+    let mut parts = vec![];
+    let mut s = String::new();
+    let mut l = loc.incr();
+    loop {
+        let next = chars.get(l.index + 1);
+        l = match chars.get(l.index) {
+            None => return Err(TokenError::UnterminatedString { line: loc.line }),
+            Some(c) if *c == quote => break,
+            Some('\\') => {
+                let (c, new_loc) = parse_escape(chars, &l)?;
+                s.push(c);
+                new_loc
+            }
+            Some('$') if quote == '"' && next == Some(&'{') => {
+                let start = l.incr2();
+                let mut end = start;
+                let mut depth = 0;
+                loop {
+                    end = match chars.get(end.index) {
+                        None => {
+                            return Err(TokenError::UnterminatedInterpolation { line: l.line });
+                        }
+                        Some('}') if depth == 0 => break,
+                        Some('}') => {
+                            depth -= 1;
+                            end.incr()
+                        }
+                        Some('{') => {
+                            depth += 1;
+                            end.incr()
+                        }
+                        // 跳过里面的字符串, 字符串里的括号不算
+                        Some('"') | Some('\'') => parse_string(chars, &end)?.1,
+                        Some('\n') => end.new_line(),
+                        Some(_) => end.incr(),
+                    };
+                }
+                let code: String = chars[start.index..end.index].iter().collect();
+                parts.push(TemplatePart::Str(std::mem::take(&mut s)));
+                parts.push(TemplatePart::Expr(tokenlizer(code)?));
+                end.incr()
+            }
+            Some('\n') => {
+                s.push('\n');
+                l.new_line()
+            }
+            Some(c) => {
+                s.push(*c);
+                l.incr()
+            }
+        };
+    }
+
+    let token = if parts.is_empty() {
+        Token::String(s)
+    } else {
+        if !s.is_empty() {
+            parts.push(TemplatePart::Str(s));
+        }
+        Token::Template(parts)
+    };
+    Ok((token, l.incr()))
+}
+
+/// 分析字符串里的转义字符, `loc` 指向反斜杠
+///
+/// 支持 `\n` `\t` `\r` `\"` `\'` `\\` `\$` 和 `\u{XXXX}`
+fn parse_escape(chars: &[char], loc: &Location) -> Result<(char, Location), TokenError> {
+    let invalid = |escape: &[char]| TokenError::InvalidEscape {
+        escape: escape.iter().collect(),
+        line: loc.line,
+    };
+    let c = match chars.get(loc.index + 1) {
+        Some('n') => '\n',
+        Some('t') => '\t',
+        Some('r') => '\r',
+        Some('"') => '"',
+        Some('\'') => '\'',
+        Some('\\') => '\\',
+        Some('$') => '$',
+        Some('u') if chars.get(loc.index + 2) == Some(&'{') => {
+            let start = loc.index + 3;
+            let end = chars[start..]
+                .iter()
+                .position(|it| *it == '}')
+                .map(|it| start + it)
+                .ok_or_else(|| invalid(&chars[loc.index..start]))?;
+            let hex: String = chars[start..end].iter().collect();
+            return u32::from_str_radix(&hex, 16)
+                .ok()
+                .and_then(char::from_u32)
+                .map(|c| (c, loc.incr_n(end + 1 - loc.index)))
+                .ok_or_else(|| invalid(&chars[loc.index..=end]));
+        }
+        _ => {
+            let end = (loc.index + 2).min(chars.len());
+            return Err(invalid(&chars[loc.index..end]));
+        }
+    };
+    Ok((c, loc.incr2()))
 }
 
 /// 代码转成token串
 pub fn tokenlizer(code: String) -> Result<Vec<Token>, TokenError> {
+    Ok(tokenlizer_with_line(code)?
+        .into_iter()
+        .map(|(token, _)| token)
+        .collect())
+}
+
+/// 词法分析, 同时返回每个 token 所在的行号 (从 1 开始)
+pub fn tokenlizer_with_line(code: String) -> Result<Vec<(Token, usize)>, TokenError> {
     let chars: Vec<_> = code.chars().collect();
 
     let mut tokens = vec![];
 
     let mut loc = Location::default();
     while loc.index < chars.len() {
-        let (token, new_loc) = parse_token(&chars, &loc)?;
+        let (token, new_loc) = parse_token(&chars, &loc).map_err(|err| err.at(loc, &chars))?;
         if !matches!(token, Token::Comment | Token::Space) {
-            tokens.push(token);
+            tokens.push((token, loc.line));
         }
         loc = new_loc;
     }
@@ -268,28 +502,53 @@ impl Location {
         }
     }
 
+    /// 用行号和列号 (都从 1 开始) 构造位置, `index` 是在源代码字符数组里的下标
+    pub fn new(line: usize, col: usize, index: usize) -> Location {
+        Location { col, line, index }
+    }
+
+    /// 打印出错的那一行, 并在下一行用 `^` 指出出错的位置, tab 按 4 列计算
     pub fn debug<S: Into<String>>(&self, raw: &[char], msg: S) -> String {
-        let mut line = 0;
-        let mut line_str = String::new();
-        // Find the whole line of original source
-        for c in raw {
-            if *c == '\n' {
-                line += 1;
-
-                // Done discovering line in question
-                if !line_str.is_empty() {
-                    break;
-                }
+        self.debug_with_tab_width(raw, msg, 4)
+    }
 
-                continue;
-            }
+    /// 同 [`Location::debug`], tab 会展开成空格, 对齐到 `tab_width` 的整数倍,
+    /// 这样不管终端的 tab 宽度是多少, `^` 都能对准
+    pub fn debug_with_tab_width<S: Into<String>>(
+        &self,
+        raw: &[char],
+        msg: S,
+        tab_width: usize,
+    ) -> String {
+        let tab_width = tab_width.max(1);
+        // 找到出错的那一行
+        let line_chars = raw
+            .split(|c| *c == '\n')
+            .nth(self.line.saturating_sub(1))
+            .unwrap_or_default();
 
-            if self.line == line {
-                line_str.push_str(&c.to_string());
+        let mut line_str = String::new();
+        let mut caret_col = None;
+        let mut width = 0;
+        for (idx, c) in line_chars.iter().enumerate() {
+            if idx + 1 == self.col {
+                caret_col = Some(width);
+            }
+            match c {
+                '\t' => {
+                    let n = tab_width - width % tab_width;
+                    line_str.push_str(&" ".repeat(n));
+                    width += n;
+                }
+                '\r' => {}
+                c => {
+                    line_str.push(*c);
+                    width += 1;
+                }
             }
         }
 
-        let space = " ".repeat(self.col as usize);
+        let space = " ".repeat(caret_col.unwrap_or(width));
         format!("{}\n\n{}\n{}^ Near here", msg.into(), line_str, space)
     }
 }
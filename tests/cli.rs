@@ -0,0 +1,104 @@
+use std::path::PathBuf;
+use std::process::Command;
+
+use pretty_assertions::assert_eq;
+
+fn write_code(name: &str, code: &str) -> PathBuf {
+    let path = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(name);
+    std::fs::write(&path, code).unwrap();
+    path
+}
+
+#[test]
+fn test_emit_tokens() {
+    let path = write_code("emit_tokens.ch", "let a = 1\nprintln(a)\n");
+    let output = Command::new(env!("CARGO_BIN_EXE_chen_lang"))
+        .args(["run", "--emit-tokens"])
+        .arg(&path)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        r#"1	Keyword(LET)
+1	Identifier("a")
+1	Operator(Assign)
+1	Int(1)
+1	NewLine
+2	StdFunction(Print(true))
+2	LParen
+2	Identifier("a")
+2	RParen
+2	NewLine
+"#
+    );
+}
+
+#[test]
+fn test_emit_tokens_conflicts_with_time() {
+    let path = write_code("emit_tokens_time.ch", "let a = 1\n");
+    let output = Command::new(env!("CARGO_BIN_EXE_chen_lang"))
+        .args(["run", "--emit-tokens", "--time"])
+        .arg(&path)
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(2));
+    assert!(output.stdout.is_empty());
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("cannot be used with"));
+}
+
+#[test]
+fn test_exit_code() {
+    let path = write_code("ok.ch", "let a = 1\nprintln(a)\n");
+    let output = Command::new(env!("CARGO_BIN_EXE_chen_lang"))
+        .arg("run")
+        .arg(&path)
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "1\n");
+
+    let path = write_code("fail.ch", "let a = 1 / 0\n");
+    let output = Command::new(env!("CARGO_BIN_EXE_chen_lang"))
+        .arg("run")
+        .arg(&path)
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "error: 除数不能为 0\n"
+    );
+}
+
+#[test]
+fn test_token_error_shows_source_line() {
+    let path = write_code("token_error.ch", "let a = 1\r\nlet b = @\r\n");
+    let output = Command::new(env!("CARGO_BIN_EXE_chen_lang"))
+        .arg("run")
+        .arg(&path)
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "error: UnknownToken '@'\n\nlet b = @\n        ^ Near here\n"
+    );
+}
+
+#[test]
+fn test_lint() {
+    let path = write_code("lint.ch", "let a = 1\nlet b = 2\nprintln(a)\n");
+    let output = Command::new(env!("CARGO_BIN_EXE_chen_lang"))
+        .arg("lint")
+        .arg(&path)
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "warning: 第 2 行: 变量 b 声明了但是没有使用\n"
+    );
+}